@@ -1,13 +1,17 @@
 use std::collections::HashMap;
 
+use near_contract_standards::upgrade::Ownable;
 use near_sdk::assert_one_yocto;
+use near_sdk::json_types::U64;
 
-use crate::constants::*;
 use crate::contract_interfaces::AccountAction;
+use crate::legacy::AccountV1;
+use crate::token_receiver::TransferOrigin;
 use crate::*;
 
 #[derive(BorshDeserialize, BorshSerialize)]
 pub enum VAccount {
+    V1(AccountV1),
     Current(Account),
 }
 
@@ -16,6 +20,7 @@ impl VAccount {
     #[allow(unused_variables)]
     pub fn into_current(self, account_id: &AccountId) -> Account {
         match self {
+            VAccount::V1(account) => account.into(),
             VAccount::Current(account) => account,
         }
     }
@@ -27,7 +32,7 @@ impl From<Account> for VAccount {
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub struct Account {
     pub near_amount_for_storage: Balance,
     // only record token in whitelist,so HashMap is ok.
@@ -38,6 +43,31 @@ pub struct Account {
     /// ft_transfer_lock will minus one when ft_transfer_resolved.
     /// a user can call storage_unregister only if ft_transfer_lock eq zero.
     pub ft_transfer_lock: u32,
+    /// how many times the account has converted tokens
+    pub conversion_count: u64,
+    /// pools in which quota usages are stored for the account
+    pub quota_pool_ids: Vec<PoolId>,
+    /// transfers to the account which are not resolved yet
+    pub in_flight_transfers: Vec<InFlightTransfer>,
+    /// nonce of the next transfer to the account
+    pub next_transfer_nonce: u64,
+    /// part of near_amount_for_storage paid by a storage sponsor, which can't be withdrawn by the account
+    pub sponsored_storage_amount: Balance,
+    /// pool whose budget sponsored the storage, none means the budget of owner
+    pub storage_sponsor_pool_id: Option<PoolId>,
+    /// bytes used by the account record and its quota usages, measured by env::storage_usage
+    pub storage_usage: StorageUsage,
+    /// the account is unregistered by force and will be deleted when its transfers are resolved
+    pub is_unregistering: bool,
+}
+
+/// a transfer created in `internal_send_tokens` and cleared in `ft_transfer_resolved`
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InFlightTransfer {
+    pub nonce: U64,
+    pub token_id: AccountId,
+    pub amount: U128,
 }
 
 impl Account {
@@ -46,10 +76,30 @@ impl Account {
             near_amount_for_storage: 0,
             tokens: HashMap::new(),
             ft_transfer_lock: 0,
+            conversion_count: 0,
+            quota_pool_ids: vec![],
+            in_flight_transfers: vec![],
+            next_transfer_nonce: 0,
+            storage_sponsor_pool_id: None,
+            sponsored_storage_amount: 0,
+            storage_usage: 0,
+            is_unregistering: false,
         }
     }
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl Account {
     pub fn deposit_token(&mut self, token_id: &AccountId, amount: Balance) {
+        assert!(
+            !self.is_unregistering,
+            "The account is being unregistered, no token can be deposited."
+        );
         self.tokens.insert(
             token_id.clone(),
             amount + self.tokens.get(token_id).unwrap_or(&0),
@@ -65,20 +115,13 @@ impl Account {
         return balance;
     }
 
-    pub fn storage_usage(&self) -> u64 {
-        INIT_ACCOUNT_STORAGE + self.tokens.len() as u64 * (ACC_ID_AS_KEY_STORAGE + U128_STORAGE)
-    }
-
     pub fn storage_cost(&self) -> Balance {
-        self.storage_usage() as u128 * env::storage_byte_cost()
+        self.storage_usage as u128 * env::storage_byte_cost()
     }
 
     pub fn available_storage_deposit(&self) -> Balance {
-        if self.near_amount_for_storage > self.storage_cost() {
-            self.near_amount_for_storage - self.storage_cost()
-        } else {
-            0
-        }
+        let reserved = std::cmp::max(self.storage_cost(), self.sponsored_storage_amount);
+        self.near_amount_for_storage.saturating_sub(reserved)
     }
 
     pub fn plus_ft_transfer_lock(&mut self) {
@@ -88,13 +131,36 @@ impl Account {
     pub fn minus_ft_transfer_lock(&mut self) {
         self.ft_transfer_lock -= 1
     }
+
+    /// record a transfer to the account and lock the account until it's resolved.
+    pub fn start_transfer(&mut self, token_id: &AccountId, amount: Balance) -> u64 {
+        let nonce = self.next_transfer_nonce;
+        self.next_transfer_nonce += 1;
+        self.in_flight_transfers.push(InFlightTransfer {
+            nonce: U64(nonce),
+            token_id: token_id.clone(),
+            amount: U128(amount),
+        });
+        self.plus_ft_transfer_lock();
+        nonce
+    }
+
+    /// clear the transfer and unlock the account,
+    /// nonce is none for transfers started before transfers were recorded.
+    pub fn finish_transfer(&mut self, nonce: Option<u64>) {
+        if let Some(nonce) = nonce {
+            self.in_flight_transfers
+                .retain(|transfer| transfer.nonce.0 != nonce);
+        }
+        self.minus_ft_transfer_lock();
+    }
 }
 
 impl TokenConvertor {
     pub(crate) fn internal_check_ft_transfer_is_lock(&self, account_id: &AccountId) -> bool {
         return self
             .internal_get_account(account_id)
-            .expect(format!("not found account #{}", account_id).as_str())
+            .unwrap_or_else(|| panic!("not found account #{}", account_id))
             .ft_transfer_lock
             > 0;
     }
@@ -112,19 +178,119 @@ impl TokenConvertor {
     {
         let mut account = self
             .internal_get_account(account_id)
-            .expect(format!("No such account #{}.", account_id).as_str());
+            .unwrap_or_else(|| panic!("No such account #{}.", account_id));
         let r = f(&mut account);
         self.internal_save_account(account_id, account);
         r
     }
 
-    pub(crate) fn internal_save_account(&mut self, account_id: &AccountId, account: Account) {
-        assert!(
-            account.storage_cost() <= account.near_amount_for_storage,
-            "Need pay {} yoctoNear for storage.",
-            account.storage_cost() - account.near_amount_for_storage
+    /// credit tokens to the account, or record them as lost and found
+    /// if the account isn't registered, is being unregistered or its storage deposit isn't enough.
+    pub(crate) fn internal_credit_account(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        if let Some(mut account) = self
+            .internal_get_account(account_id)
+            .filter(|account| !account.is_unregistering)
+        {
+            account.deposit_token(token_id, amount);
+            if self.internal_try_save_account(account_id, account).is_ok() {
+                return;
+            }
+        }
+        self.internal_record_lost_found(account_id, token_id, amount);
+    }
+
+    pub(crate) fn internal_record_lost_found(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let mut tokens = self.lost_found.get(account_id).unwrap_or_default();
+        tokens.insert(
+            token_id.clone(),
+            amount + tokens.get(token_id).unwrap_or(&0),
         );
+        self.lost_found.insert(account_id, &tokens);
+        log!(
+            "{} of token '{}' is recorded as lost and found of '{}'.",
+            amount,
+            token_id,
+            account_id
+        );
+    }
+
+    pub(crate) fn internal_save_account(&mut self, account_id: &AccountId, account: Account) {
+        if let Err(lacking_amount) = self.internal_try_save_account(account_id, account) {
+            panic!("Need pay {} yoctoNear for storage.", lacking_amount);
+        }
+    }
+
+    /// write the account and measure the storage it uses by env::storage_usage.
+    /// if its storage deposit can't pay the storage, the stored account is restored
+    /// and the lacking amount is returned.
+    pub(crate) fn internal_try_save_account(
+        &mut self,
+        account_id: &AccountId,
+        mut account: Account,
+    ) -> Result<(), Balance> {
+        let old_account = self.accounts.get(account_id);
+        let is_legacy = matches!(old_account, Some(VAccount::V1(_)));
+        let initial_storage_usage = env::storage_usage();
+        self.accounts
+            .insert(account_id, &VAccount::Current(account.clone()));
+        // accounts in the released version are not charged when migrated,
+        // the growth of their record is paid by the contract.
+        if !is_legacy {
+            account.storage_usage = (account.storage_usage + env::storage_usage())
+                .saturating_sub(initial_storage_usage);
+        }
+        if account.storage_cost() > account.near_amount_for_storage && !is_legacy {
+            match &old_account {
+                Some(old_account) => self.accounts.insert(account_id, old_account),
+                None => self.accounts.remove(account_id),
+            };
+            return Err(account.storage_cost() - account.near_amount_for_storage);
+        }
+        if let Some(VAccount::V1(old_account)) = old_account {
+            // token balances of accounts in the released version are counted when migrated,
+            // see `migrate_accounts`.
+            for (token_id, amount) in old_account.tokens {
+                self.internal_increase_liability(&token_id, amount);
+            }
+        }
+        // the size of storage_usage is fixed, so the record is rewritten in place.
         self.accounts.insert(account_id, &account.into());
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl TokenConvertor {
+    /// rewrite accounts of the released version into the current version and count their token balances
+    /// into liabilities. accounts can't be enumerated, so their ids are given by the caller in batches.
+    /// return the number of migrated accounts.
+    pub fn migrate_accounts(&mut self, account_ids: Vec<AccountId>) -> u32 {
+        let mut count = 0;
+        for account_id in account_ids {
+            if let Some(VAccount::V1(_)) = self.accounts.get(&account_id) {
+                self.internal_use_account(&account_id, |_| {});
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// owner declares that all accounts of the released version are migrated by `migrate_accounts`,
+    /// liabilities are complete after it and pools are migrated.
+    pub fn finish_migrating_accounts(&mut self) {
+        self.assert_owner();
+        self.legacy_accounts_migrated = true;
+        log!("All accounts of the released version are migrated.");
     }
 }
 
@@ -138,7 +304,31 @@ impl AccountAction for TokenConvertor {
             return account.withdraw_all_token(&token_id);
         });
         if balance > 0 {
-            self.internal_send_tokens(&env::predecessor_account_id(), &token_id, balance);
+            self.internal_send_tokens(
+                &env::predecessor_account_id(),
+                &token_id,
+                balance,
+                TransferOrigin::Account,
+            );
+        }
+    }
+
+    #[payable]
+    fn claim_lost_found(&mut self, token_id: AccountId) {
+        self.assert_contract_is_not_paused();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut tokens = self.lost_found.get(&account_id).unwrap_or_default();
+        let amount = tokens
+            .remove(&token_id)
+            .unwrap_or_else(|| panic!("No lost and found of token '{}'.", token_id));
+        self.internal_use_account(&account_id, |account| {
+            account.deposit_token(&token_id, amount)
+        });
+        if tokens.is_empty() {
+            self.lost_found.remove(&account_id);
+        } else {
+            self.lost_found.insert(&account_id, &tokens);
         }
     }
 }
@@ -0,0 +1,182 @@
+use crate::contract_interfaces::PoolAllowlistAction;
+use crate::conversion_pool::ConversionPool;
+use crate::events::{EventEmit, PoolEvent};
+use crate::*;
+use near_sdk::collections::UnorderedSet;
+
+impl TokenConvertor {
+    pub(crate) fn internal_is_allowed_in_pool(
+        &self,
+        pool: &ConversionPool,
+        account_id: &AccountId,
+    ) -> bool {
+        !pool.is_private
+            || self
+                .pool_allowlists
+                .get(&pool.id)
+                .is_some_and(|allowlist| allowlist.contains(account_id))
+    }
+}
+
+#[near_bindgen]
+impl PoolAllowlistAction for TokenConvertor {
+    fn set_pool_private(&mut self, pool_id: PoolId, is_private: bool) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        self.internal_use_pool(pool_id, |pool| pool.is_private = is_private);
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+    }
+
+    #[payable]
+    fn add_accounts_to_pool_allowlist(&mut self, pool_id: PoolId, account_ids: Vec<AccountId>) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        if env::attached_deposit() > 0 {
+            self.internal_use_pool(pool_id, |pool| {
+                pool.deposit_near_amount =
+                    U128(pool.deposit_near_amount.0 + env::attached_deposit())
+            });
+        }
+        let initial_storage_usage = env::storage_usage();
+        let mut allowlist = self
+            .pool_allowlists
+            .get(&pool_id)
+            .unwrap_or_else(|| UnorderedSet::new(StorageKey::PoolAllowlist { pool_id }));
+        for account_id in account_ids {
+            allowlist.insert(&account_id);
+        }
+        self.pool_allowlists.insert(&pool_id, &allowlist);
+        self.internal_charge_pool_storage(&pool_id, initial_storage_usage);
+    }
+
+    fn remove_accounts_from_pool_allowlist(
+        &mut self,
+        pool_id: PoolId,
+        account_ids: Vec<AccountId>,
+    ) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        if let Some(mut allowlist) = self.pool_allowlists.get(&pool_id) {
+            let initial_storage_usage = env::storage_usage();
+            for account_id in account_ids {
+                allowlist.remove(&account_id);
+            }
+            if allowlist.is_empty() {
+                self.pool_allowlists.remove(&pool_id);
+            } else {
+                self.pool_allowlists.insert(&pool_id, &allowlist);
+            }
+            self.internal_charge_pool_storage(&pool_id, initial_storage_usage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract_interfaces::{ConvertorViewer, PoolCreatorAction};
+    use crate::test::{setup_contract, usdc, usdt};
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_pool_allowlist() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut pool = ConversionPool::new(
+            U64(1),
+            bob(),
+            usdc(),
+            usdt(),
+            false,
+            1,
+            1,
+            U128(10u128.pow(24)),
+        );
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        assert!(contract.is_allowed_in_pool(U64(1), alice()));
+
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_private(U64(1), true);
+        assert!(!contract.is_allowed_in_pool(U64(1), alice()));
+
+        contract.add_accounts_to_pool_allowlist(U64(1), vec![alice(), bob()]);
+        assert!(contract.is_allowed_in_pool(U64(1), alice()));
+        assert_eq!(contract.get_pool_allowlist(U64(1), 0, 10).len(), 2);
+        contract.internal_convert(U64(1), &alice(), &usdc(), 10);
+
+        contract.remove_accounts_from_pool_allowlist(U64(1), vec![alice(), bob()]);
+        assert!(contract.pool_allowlists.get(&U64(1)).is_none());
+        assert!(!contract.is_allowed_in_pool(U64(1), alice()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Failed to delete pool '1'. All accounts in the allowlist of the pool must be removed first."
+    )]
+    fn test_delete_pool_with_allowlist() {
+        let (mut context, mut contract, _) = setup_contract();
+        let pool = ConversionPool::new(
+            U64(1),
+            bob(),
+            usdc(),
+            usdt(),
+            false,
+            1,
+            1,
+            U128(10u128.pow(24)),
+        );
+        contract.pools.insert(&U64(1), &pool.into());
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.add_accounts_to_pool_allowlist(U64(1), vec![alice()]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.delete_pool(U64(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "'alice.near' is not allowed to convert in private pool '1'.")]
+    fn test_convert_in_private_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut pool = ConversionPool::new(
+            U64(1),
+            bob(),
+            usdc(),
+            usdt(),
+            false,
+            1,
+            1,
+            U128(10u128.pow(24)),
+        );
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_private(U64(1), true);
+        contract.internal_convert(U64(1), &alice(), &usdc(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract owner or pool creator can manage the pool.")]
+    fn test_manage_allowlist_by_others() {
+        let (mut context, mut contract, _) = setup_contract();
+        let pool = ConversionPool::new(
+            U64(1),
+            bob(),
+            usdc(),
+            usdt(),
+            false,
+            1,
+            1,
+            U128(10u128.pow(24)),
+        );
+        contract.pools.insert(&U64(1), &pool.into());
+        testing_env!(context.predecessor_account_id(alice()).build());
+        contract.add_accounts_to_pool_allowlist(U64(1), vec![alice()]);
+    }
+}
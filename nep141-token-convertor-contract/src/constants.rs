@@ -1,11 +1,19 @@
-use near_sdk::StorageUsage;
+use near_sdk::{StorageUsage, Timestamp};
 
 pub const T_GAS_FOR_FT_TRANSFER: u64 = 10;
 pub const T_GAS_FOR_RESOLVE_TRANSFER: u64 = 20;
+pub const T_GAS_FOR_MIGRATE: u64 = 100;
+pub const T_GAS_FOR_FT_METADATA: u64 = 10;
+pub const T_GAS_FOR_RESOLVE_FT_METADATA: u64 = 10;
+pub const T_GAS_FOR_FT_BALANCE_OF: u64 = 10;
+pub const T_GAS_FOR_RESOLVE_RECONCILE: u64 = 10;
+/// transferring surplus and resolving the transfer are included
+pub const T_GAS_FOR_RESOLVE_RECOVER_SURPLUS: u64 =
+    10 + T_GAS_FOR_FT_TRANSFER + T_GAS_FOR_RESOLVE_TRANSFER;
 // pub const GAS_FOR_FT_TRANSFER_CALL: u64 = 25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER;
 
 pub const U128_STORAGE: StorageUsage = 16;
-// const U64_STORAGE: StorageUsage = 8;
+pub const U64_STORAGE: StorageUsage = 8;
 pub const U32_STORAGE: StorageUsage = 4;
 /// max length of account id is 64 bytes. We charge per byte.
 pub const ACC_ID_STORAGE: StorageUsage = 64;
@@ -13,15 +21,88 @@ pub const ACC_ID_STORAGE: StorageUsage = 64;
 pub const ACC_ID_AS_KEY_STORAGE: StorageUsage = ACC_ID_STORAGE + 4;
 /// As a near_sdk::collection key, 1 byte for prefix
 pub const ACC_ID_AS_CLT_KEY_STORAGE: StorageUsage = ACC_ID_AS_KEY_STORAGE + 1;
+/// each record in the storage costs 40 bytes besides its key and value
+pub const STORAGE_RECORD_OVERHEAD: StorageUsage = 40;
 
+/// The storage used by accounts is measured by env::storage_usage,
+/// the following sizes are only upper bounds to decide the minimum storage deposit.
+///
+/// STORAGE_RECORD_OVERHEAD: the Contract accounts map record
+/// + ACC_ID: the Contract accounts map key length
+/// + VAccount enum: 1 byte
+/// + U128_STORAGE: near_amount_for_storage storage
+/// + U32_STORAGE: tokens HashMap length
+/// + U32_STORAGE: ft_transfer_lock storage
+/// + U64_STORAGE: conversion_count storage
+/// + U32_STORAGE: quota_pool_ids Vec length
+/// + U32_STORAGE: in_flight_transfers Vec length
+/// + U64_STORAGE: next_transfer_nonce storage
+/// + U128_STORAGE: sponsored_storage_amount storage
+/// + 1 byte + U64_STORAGE: storage_sponsor_pool_id storage
+/// + U64_STORAGE: storage_usage storage
+/// + 1 byte: is_unregistering storage
+pub const INIT_ACCOUNT_STORAGE: StorageUsage = STORAGE_RECORD_OVERHEAD
+    + ACC_ID_AS_CLT_KEY_STORAGE
+    + 1
+    + U32_STORAGE
+    + U32_STORAGE
+    + U128_STORAGE
+    + U64_STORAGE
+    + U32_STORAGE
+    + U32_STORAGE
+    + U64_STORAGE
+    + U128_STORAGE
+    + 1
+    + U64_STORAGE
+    + U64_STORAGE
+    + 1;
+
+/// the storage charged for an account by the released version:
 /// ACC_ID: the Contract accounts map key length
 /// + VAccount enum: 1 byte
 /// + U128_STORAGE: near_amount_for_storage storage
 /// + U32_STORAGE: tokens HashMap length
 /// + U32_STORAGE: ft_transfer_lock storage
-pub const INIT_ACCOUNT_STORAGE: StorageUsage =
+pub const INIT_ACCOUNT_V1_STORAGE: StorageUsage =
     ACC_ID_AS_CLT_KEY_STORAGE + 1 + U32_STORAGE + U32_STORAGE + U128_STORAGE;
 
+/// an in-flight transfer of an account: nonce + token id with 4 bytes length + amount
+pub const IN_FLIGHT_TRANSFER_STORAGE: StorageUsage =
+    U64_STORAGE + ACC_ID_AS_KEY_STORAGE + U128_STORAGE;
+
+/// the account in the set of quota accounts of a pool, which stores 2 records:
+/// record overhead
+/// key: 1 byte prefix + PoolId + 1 byte suffix + AccountId with 4 bytes length or index
+/// value: index or AccountId with 4 bytes length
+const POOL_QUOTA_ACCOUNT_STORAGE: StorageUsage =
+    2 * (STORAGE_RECORD_OVERHEAD + 1 + U64_STORAGE + 1 + ACC_ID_AS_KEY_STORAGE + U64_STORAGE);
+
+/// the set of quota accounts of a pool, paid by its first account:
+/// record overhead
+/// key: 1 byte prefix + PoolId
+/// value: 2 prefixes of the set with 4 bytes length + length of the set
+const POOL_QUOTA_ACCOUNTS_STORAGE: StorageUsage = STORAGE_RECORD_OVERHEAD
+    + 1
+    + U64_STORAGE
+    + 2 * (U32_STORAGE + 1 + U64_STORAGE + 1)
+    + U64_STORAGE;
+
+/// a quota usage entry of an account in a pool:
+/// record overhead
+/// key: 1 byte prefix + PoolId + AccountId with 4 bytes length
+/// value: window_start + used_amount
+/// + PoolId in quota_pool_ids of the account
+/// + POOL_QUOTA_ACCOUNT_STORAGE + POOL_QUOTA_ACCOUNTS_STORAGE
+pub const QUOTA_USAGE_STORAGE: StorageUsage = STORAGE_RECORD_OVERHEAD
+    + 1
+    + U64_STORAGE
+    + ACC_ID_AS_KEY_STORAGE
+    + U64_STORAGE
+    + U128_STORAGE
+    + U64_STORAGE
+    + POOL_QUOTA_ACCOUNT_STORAGE
+    + POOL_QUOTA_ACCOUNTS_STORAGE;
+
 /// Defining PREPAY_STORAGE is the maximum StorageUsage that can be occupied after any contract interfaces executing
 /// now the maximum StorageUsage is delete_pool:
 /// -if user has registered, it may add 2 entry into HashMap<AccountId, Balance>.
@@ -31,3 +112,9 @@ pub const PREPAY_STORAGE_FOR_REGISTERED: StorageUsage = 2 * (ACC_ID_AS_KEY_STORA
 /// if user haven't registered, should add INIT_ACCOUNT_STORAGE.
 pub const PREPAY_STORAGE_FOR_UNREGISTERED: StorageUsage =
     INIT_ACCOUNT_STORAGE + PREPAY_STORAGE_FOR_REGISTERED;
+
+/// version of the contract state, the released version doesn't record it.
+pub const STATE_VERSION: u32 = 2;
+
+/// staged code can only be deployed after it has been staged for one day.
+pub const STAGED_CODE_DELAY_IN_NANOS: Timestamp = 24 * 60 * 60 * 1_000_000_000;
@@ -1,28 +1,108 @@
-use crate::account::AccountView;
-use crate::conversion_pool::ConversionPool;
+use crate::account::{AccountView, InFlightTransfer};
+use crate::conversion_pool::{
+    ConversionPool, ConversionQuote, InTokenRetirement, PoolStatus, TimeWindow,
+};
+use crate::quota::UserQuotaView;
 use crate::{FtMetaData, PoolId};
-use near_sdk::json_types::U128;
-use near_sdk::AccountId;
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::{AccountId, Promise};
+use std::collections::HashMap;
 
 pub trait ConvertorViewer {
-    fn get_whitelist(&self) -> Vec<FtMetaData>;
+    fn get_whitelist(&self, from_index: u32, limit: u32) -> Vec<FtMetaData>;
+
+    fn get_whitelisted_token(&self, token_id: AccountId) -> Option<FtMetaData>;
+
+    /// tokens removed from whitelist but still used by some pools
+    fn get_delisted_tokens(&self) -> Vec<FtMetaData>;
+
+    /// pools that can't convert since the token is delisted
+    fn get_pools_by_delisted_token(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool>;
 
     fn get_pools(&self, from_index: u32, limit: u32) -> Vec<ConversionPool>;
 
-    fn get_pools_by_creator(&self, account_id: AccountId) -> Vec<ConversionPool>;
+    fn get_pools_by_creator(
+        &self,
+        account_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool>;
+
+    /// pools whose in_token or out_token is the token
+    fn get_pools_by_token(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool>;
+
+    /// pools created for in_token and out_token, the order of tokens matters
+    fn get_pools_by_pair(&self, in_token: AccountId, out_token: AccountId) -> Vec<ConversionPool>;
+
+    /// the maximum amount of input token allowed by limits of the pool now, none means no limit
+    fn get_pool_remaining_capacity(&self, pool_id: PoolId) -> Option<U128>;
+
+    /// pool is upcoming before start time, ended since end time, otherwise active
+    fn get_pool_status(&self, pool_id: PoolId) -> PoolStatus;
+
+    fn get_pool_allowlist(&self, pool_id: PoolId, from_index: u32, limit: u32) -> Vec<AccountId>;
+
+    /// whether the account can convert in the pool, always true for public pools
+    fn is_allowed_in_pool(&self, pool_id: PoolId, account_id: AccountId) -> bool;
+
+    /// quota of the account in the pool, none means the pool has no user quota
+    fn get_user_quota(&self, pool_id: PoolId, account_id: AccountId) -> Option<UserQuotaView>;
+
+    /// find pools which can convert `amount` of input_token into output_token,
+    /// the best pool is at the front of returned quotes.
+    /// if account_id is given, its allowlists and quotas are applied, otherwise private pools are excluded.
+    fn find_best_pool(
+        &self,
+        input_token: AccountId,
+        output_token: AccountId,
+        amount: U128,
+        account_id: Option<AccountId>,
+    ) -> Vec<ConversionQuote>;
+
+    /// sponsor budget of the pool, or of the owner when pool_id is none
+    fn get_storage_sponsor_budget(&self, pool_id: Option<PoolId>) -> U128;
+
+    /// total balances of the token in pools and accounts, which the contract should hold
+    fn get_token_liabilities(&self, token_id: AccountId) -> U128;
+
+    /// amount of the token being transferred out of the contract
+    fn get_token_in_flight_amount(&self, token_id: AccountId) -> U128;
+
+    /// tokens paused by reconciliation or owner
+    fn get_paused_tokens(&self) -> Vec<AccountId>;
 
-    /// storage fee need deposit = storage_balance_bounds.min - account.near_amount_for_storage
-    /// if account.near_amount_for_storage > storage_balance_bounds.min, it should return 0
     fn get_storage_fee_gap_of(&self, account_id: AccountId) -> U128;
 
     fn get_account(&self, account_id: AccountId) -> AccountView;
 
+    /// tokens failed to be transferred to the account which couldn't be credited to its account
+    fn get_lost_found(&self, account_id: AccountId) -> HashMap<AccountId, U128>;
+
+    /// transfers to the account which are not resolved yet, for diagnosing stuck withdrawals
+    fn get_in_flight_transfers(&self, account_id: AccountId) -> Vec<InFlightTransfer>;
+
     fn is_contract_paused(&self) -> bool;
 
     fn get_deposit_amount_of_pool_creation(&self) -> U128;
+
+    /// sha256 of the code staged by owner, reviewers can compare it with a reproducible build.
+    fn get_staged_code_hash(&self) -> Option<Base58CryptoHash>;
 }
 
 pub trait PoolCreatorAction {
+    /// conversions in pool are only allowed in the time window, none means no bound.
+    /// the deposit of pool is the storage cost of the pool, but at least create_pool_deposit,
+    /// the exceeding part of attached near is refunded.
     fn create_pool(
         &mut self,
         token_from: AccountId,
@@ -30,6 +110,7 @@ pub trait PoolCreatorAction {
         is_reversible: bool,
         in_token_rate: u32,
         out_token_rate: u32,
+        time_window: Option<TimeWindow>,
     ) -> PoolId;
 
     /// only pool creator or owner can withdraw token in pool
@@ -41,21 +122,105 @@ pub trait PoolCreatorAction {
         amount: Option<U128>,
     );
 
+    /// only pool creator or owner can pause or resume conversions in pool
+    fn pause_pool(&mut self, pool_id: PoolId);
+
+    fn resume_pool(&mut self, pool_id: PoolId);
+
+    /// only pool creator or owner can set limits of conversions in pool.
+    /// volume_limit is (window duration in nanoseconds, max volume of input token in a window).
+    fn set_pool_limits(
+        &mut self,
+        pool_id: PoolId,
+        max_conversion_amount: Option<U128>,
+        volume_limit: Option<(U64, U128)>,
+    );
+
+    /// only pool creator or owner can set quota of each account in pool.
+    /// user_quota is (window duration in nanoseconds, max amount of input token in a window).
+    fn set_pool_user_quota(&mut self, pool_id: PoolId, user_quota: Option<(U64, U128)>);
+
+    /// only pool creator or owner can make a non-reversible pool a one-way migration pool,
+    /// the input token in it can't be withdrawn and the retirement can't be changed anymore.
+    fn set_pool_in_token_retirement(&mut self, pool_id: PoolId, retirement: InTokenRetirement);
+
     fn delete_pool(&mut self, pool_id: PoolId);
+
+    /// only pool creator or owner can top up the deposit of pool to pay the storage of pool,
+    /// return the deposit of pool.
+    fn deposit_pool_storage(&mut self, pool_id: PoolId) -> U128;
+}
+
+/// only pool creator or owner can manage allowlist of pool
+pub trait PoolAllowlistAction {
+    /// public pool can be used by anyone, private pool can only be used by accounts in allowlist
+    fn set_pool_private(&mut self, pool_id: PoolId, is_private: bool);
+
+    /// attached near is added to the deposit of pool to pay the storage of allowlist
+    fn add_accounts_to_pool_allowlist(&mut self, pool_id: PoolId, account_ids: Vec<AccountId>);
+
+    fn remove_accounts_from_pool_allowlist(&mut self, pool_id: PoolId, account_ids: Vec<AccountId>);
+}
+
+/// storage of accounts which convert for the first time is paid by sponsor budget of the pool,
+/// or the budget of owner if the pool's is not enough.
+/// pool_id is none means the budget of owner.
+pub trait StorageSponsorAction {
+    /// only pool creator or owner can deposit into the budget of pool, only owner can deposit into the budget of owner
+    fn deposit_storage_sponsor_budget(&mut self, pool_id: Option<PoolId>);
+
+    /// the same as depositing, if amount is none, it means withdraw all
+    fn withdraw_storage_sponsor_budget(&mut self, pool_id: Option<PoolId>, amount: Option<U128>);
 }
 
 pub trait OwnerAction {
     fn extend_whitelisted_tokens(&mut self, tokens: Vec<FtMetaData>);
 
+    /// add token into whitelist by metadata fetched from the token contract,
+    /// the token will be rejected if it fails to get metadata.
+    fn whitelist_token(&mut self, token_id: AccountId) -> Promise;
+
+    fn set_whitelisted_token_tags(&mut self, token_id: AccountId, tags: Vec<String>);
+
+    /// it's only available after pools of the released version are migrated.
     fn remove_whitelisted_tokens(&mut self, tokens: Vec<AccountId>);
 
     fn set_deposit_amount_of_pool_creation(&mut self, amount: U128);
-    ///
+    /// transfer tokens held by the contract beyond its liabilities and tokens in flight
+    /// to the receiver, e.g. tokens sent by `ft_transfer` without calling the contract.
+    /// it's only available after pools and accounts of the released version are migrated.
+    fn recover_surplus(&mut self, token_id: AccountId, receiver_id: AccountId) -> Promise;
+
+    /// conversions with a paused token are disabled
+    fn pause_token(&mut self, token_id: AccountId);
+
+    fn resume_token(&mut self, token_id: AccountId);
+    /// all change methods except owner's are disabled when the contract is paused
     fn pause_contract(&mut self);
-    ///
+
     fn resume_contract(&mut self);
 }
 
+pub trait SolvencyAction {
+    /// anyone can compare the balance of the token held by the contract with its liabilities,
+    /// the token is paused if the contract is under-collateralized.
+    /// it's only available after pools and accounts of the released version are migrated.
+    fn reconcile(&mut self, token_id: AccountId) -> Promise;
+}
+
 pub trait AccountAction {
     fn withdraw_token_in_account(&mut self, token_id: AccountId);
+
+    /// move the lost and found token of the caller into its account, then it can be withdrawn.
+    fn claim_lost_found(&mut self, token_id: AccountId);
+}
+
+pub trait UpgradeAction {
+    /// stage wasm code for upgrading, the code is read from raw input.
+    /// staging again will replace the staged code and restart the delay.
+    fn stage_code(&mut self);
+
+    /// deploy the staged code to this contract,
+    /// only available after the code has been staged for `STAGED_CODE_DELAY_IN_NANOS`.
+    fn deploy_staged_code(&mut self) -> Promise;
 }
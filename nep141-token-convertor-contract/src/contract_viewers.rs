@@ -1,12 +1,38 @@
-use crate::account::AccountView;
+use crate::account::{AccountView, InFlightTransfer};
 use crate::contract_interfaces::ConvertorViewer;
-use crate::conversion_pool::ConversionPool;
+use crate::conversion_pool::{ConversionPool, ConversionQuote, PoolStatus};
+use crate::quota::UserQuotaView;
 use crate::*;
+use near_sdk::json_types::Base58CryptoHash;
 
 #[near_bindgen]
 impl ConvertorViewer for TokenConvertor {
-    fn get_whitelist(&self) -> Vec<FtMetaData> {
-        self.whitelisted_tokens.values().collect_vec()
+    fn get_whitelist(&self, from_index: u32, limit: u32) -> Vec<FtMetaData> {
+        self.whitelisted_tokens
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect_vec()
+    }
+
+    fn get_whitelisted_token(&self, token_id: AccountId) -> Option<FtMetaData> {
+        self.whitelisted_tokens.get(&token_id)
+    }
+
+    fn get_delisted_tokens(&self) -> Vec<FtMetaData> {
+        self.delisted_tokens.values().collect_vec()
+    }
+
+    fn get_pools_by_delisted_token(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool> {
+        if self.delisted_tokens.get(&token_id).is_none() {
+            return vec![];
+        }
+        self.get_pools_by_token(token_id, from_index, limit)
     }
 
     fn get_pools(&self, from_index: u32, limit: u32) -> Vec<ConversionPool> {
@@ -18,16 +44,104 @@ impl ConvertorViewer for TokenConvertor {
             .collect_vec()
     }
 
-    fn get_pools_by_creator(&self, account_id: AccountId) -> Vec<ConversionPool> {
-        self.pools
-            .iter()
-            .map(|e| e.1.into_current())
-            .filter(|e| e.creator == account_id)
-            .collect_vec()
+    fn get_pools_by_creator(
+        &self,
+        account_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool> {
+        self.internal_get_pools_by_ids(self.pool_index.get_pool_ids_by_creator(
+            &account_id,
+            from_index,
+            limit,
+        ))
+    }
+
+    fn get_pools_by_token(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool> {
+        self.internal_get_pools_by_ids(
+            self.pool_index
+                .get_pool_ids_by_token(&token_id, from_index, limit),
+        )
+    }
+
+    fn get_pools_by_pair(&self, in_token: AccountId, out_token: AccountId) -> Vec<ConversionPool> {
+        self.internal_get_pools_by_ids(self.pool_index.get_pool_ids_by_pair(&in_token, &out_token))
+    }
+
+    fn get_pool_remaining_capacity(&self, pool_id: PoolId) -> Option<U128> {
+        self.internal_get_pool(&pool_id)
+            .expect("No such pool.")
+            .get_remaining_capacity()
+            .map(U128)
+    }
+
+    fn get_pool_status(&self, pool_id: PoolId) -> PoolStatus {
+        self.internal_get_pool(&pool_id)
+            .expect("No such pool.")
+            .get_status()
+    }
+
+    fn get_pool_allowlist(&self, pool_id: PoolId, from_index: u32, limit: u32) -> Vec<AccountId> {
+        self.pool_allowlists
+            .get(&pool_id)
+            .map(|allowlist| {
+                allowlist
+                    .iter()
+                    .skip(from_index as usize)
+                    .take(limit as usize)
+                    .collect_vec()
+            })
+            .unwrap_or_default()
+    }
+
+    fn is_allowed_in_pool(&self, pool_id: PoolId, account_id: AccountId) -> bool {
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_is_allowed_in_pool(&pool, &account_id)
+    }
+
+    fn get_user_quota(&self, pool_id: PoolId, account_id: AccountId) -> Option<UserQuotaView> {
+        self.internal_get_user_quota(&pool_id, &account_id)
+    }
+
+    fn find_best_pool(
+        &self,
+        input_token: AccountId,
+        output_token: AccountId,
+        amount: U128,
+        account_id: Option<AccountId>,
+    ) -> Vec<ConversionQuote> {
+        self.internal_quote_pools(&input_token, &output_token, amount.0, account_id.as_ref())
+    }
+
+    /// sponsor budget of the pool, or of the owner when pool_id is none
+    fn get_storage_sponsor_budget(&self, pool_id: Option<PoolId>) -> U128 {
+        match pool_id {
+            Some(pool_id) => {
+                self.internal_get_pool(&pool_id)
+                    .expect("No such pool.")
+                    .storage_sponsor_budget
+            }
+            None => U128(self.storage_sponsor_budget),
+        }
+    }
+
+    fn get_token_liabilities(&self, token_id: AccountId) -> U128 {
+        U128(self.internal_get_liability(&token_id))
+    }
+
+    fn get_token_in_flight_amount(&self, token_id: AccountId) -> U128 {
+        U128(self.internal_get_in_flight_amount(&token_id))
+    }
+
+    fn get_paused_tokens(&self) -> Vec<AccountId> {
+        self.paused_tokens.to_vec()
     }
 
-    /// storage fee need deposit = storage_balance_bounds.min - account.near_amount_for_storage
-    /// if account.near_amount_for_storage > storage_balance_bounds.min,it should return 0
     fn get_storage_fee_gap_of(&self, account_id: AccountId) -> U128 {
         let near_amount_for_storage = self
             .internal_get_account(&account_id)
@@ -41,6 +155,21 @@ impl ConvertorViewer for TokenConvertor {
         };
     }
 
+    fn get_lost_found(&self, account_id: AccountId) -> HashMap<AccountId, U128> {
+        self.lost_found
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(token_id, amount)| (token_id, U128(amount)))
+            .collect()
+    }
+
+    fn get_in_flight_transfers(&self, account_id: AccountId) -> Vec<InFlightTransfer> {
+        self.internal_get_account(&account_id)
+            .map(|account| account.in_flight_transfers)
+            .unwrap_or_default()
+    }
+
     fn get_account(&self, account_id: AccountId) -> AccountView {
         self.internal_get_account(&account_id)
             .expect("no such account")
@@ -54,4 +183,8 @@ impl ConvertorViewer for TokenConvertor {
     fn get_deposit_amount_of_pool_creation(&self) -> U128 {
         U128(self.create_pool_deposit)
     }
+
+    fn get_staged_code_hash(&self) -> Option<Base58CryptoHash> {
+        self.staged_code_hash.map(|hash| hash.into())
+    }
 }
@@ -1,27 +1,41 @@
+use crate::constants::{T_GAS_FOR_FT_TRANSFER, T_GAS_FOR_RESOLVE_TRANSFER};
 use crate::contract_interfaces::PoolCreatorAction;
 use crate::events::{EventEmit, PoolEvent};
+use crate::legacy::ConversionPoolV1;
+use crate::quota::UserQuota;
+use crate::token_receiver::TransferOrigin;
 use crate::types::U256;
 use crate::*;
-use near_sdk::assert_one_yocto;
+use near_contract_standards::fungible_token::core::ext_ft_core;
 use near_sdk::json_types::U64;
+use near_sdk::{assert_one_yocto, ONE_YOCTO};
+use std::ops::Mul;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub enum VPool {
-    Current(ConversionPool),
+    V1(ConversionPoolV1),
+    // boxed as the current version is much larger, it's stored the same as unboxed.
+    Current(Box<ConversionPool>),
 }
 
 impl VPool {
+    /// Upgrades from other versions to the currently used version.
     pub fn into_current(self) -> ConversionPool {
         match self {
-            VPool::Current(pool) => pool,
+            VPool::V1(pool) => pool.into(),
+            VPool::Current(pool) => *pool,
         }
     }
+
+    pub fn is_current(&self) -> bool {
+        matches!(self, VPool::Current(_))
+    }
 }
 
 impl From<ConversionPool> for VPool {
     fn from(pool: ConversionPool) -> Self {
-        VPool::Current(pool)
+        VPool::Current(Box::new(pool))
     }
 }
 
@@ -43,9 +57,155 @@ pub struct ConversionPool {
     pub out_token_rate: u32,
     /// deposit near amount when creating this pool
     pub deposit_near_amount: U128,
+    /// pool creator or owner can pause conversions in this pool
+    pub is_paused: bool,
+    pub statistics: PoolStatistics,
+    /// max amount of input token in a single conversion, none means no limit
+    pub max_conversion_amount: Option<U128>,
+    /// max volume of input token in a time window, none means no limit
+    pub volume_limit: Option<VolumeLimit>,
+    /// max amount of input token converted by each account in a time window, none means no limit
+    pub user_quota: Option<UserQuota>,
+    /// only accounts in the allowlist of pool can convert in a private pool
+    pub is_private: bool,
+    /// conversions are allowed from this block timestamp, none means no start time
+    pub start_time: Option<U64>,
+    /// conversions are not allowed since this block timestamp, none means never end
+    pub end_time: Option<U64>,
+    /// how the input token received by a one-way migration pool is retired,
+    /// none means the creator can withdraw the input token.
+    pub in_token_retirement: Option<InTokenRetirement>,
+    /// transfers of tokens in the pool which are not resolved yet,
+    /// the pool can't be withdrawn or deleted until they are resolved.
+    pub pending_transfer_count: u32,
+    /// near deposited by creator or owner to pay storage of accounts which convert in the pool for the first time
+    pub storage_sponsor_budget: U128,
+    /// bytes used by the pool record, its index entries and its allowlist, measured by env::storage_usage,
+    /// which are paid by deposit_near_amount.
+    pub storage_usage: U64,
+}
+
+/// input token of a one-way migration pool can never be withdrawn by the creator,
+/// it's either locked in the pool or forwarded to a sink account.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum InTokenRetirement {
+    Lock,
+    Forward { sink_id: AccountId },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PoolStatus {
+    Upcoming,
+    Active,
+    Ended,
+}
+
+/// conversions are only allowed in [start_time, end_time) of block timestamp in nanoseconds,
+/// none means no bound.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimeWindow {
+    pub start_time: Option<U64>,
+    pub end_time: Option<U64>,
+}
+
+/// limit the total amount of input token converted in a window of block timestamp,
+/// the window restarts at the first conversion after it ends.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VolumeLimit {
+    /// window length in nanoseconds
+    pub window_duration: U64,
+    pub max_volume: U128,
+    pub window_start: U64,
+    pub window_volume: U128,
+}
+
+impl VolumeLimit {
+    pub fn new(window_duration: U64, max_volume: U128) -> Self {
+        assert!(
+            window_duration.0 > 0,
+            "Window duration should be greater than 0."
+        );
+        Self {
+            window_duration,
+            max_volume,
+            window_start: U64(0),
+            window_volume: U128(0),
+        }
+    }
+
+    fn is_window_ended(&self) -> bool {
+        env::block_timestamp() >= self.window_start.0 + self.window_duration.0
+    }
+
+    pub fn get_remaining_volume(&self) -> Balance {
+        if self.is_window_ended() {
+            self.max_volume.0
+        } else {
+            self.max_volume.0.saturating_sub(self.window_volume.0)
+        }
+    }
+
+    pub fn record(&mut self, amount: Balance) {
+        if self.is_window_ended() {
+            self.window_start = U64(env::block_timestamp());
+            self.window_volume = U128(0);
+        }
+        assert!(
+            self.window_volume.0 + amount <= self.max_volume.0,
+            "Failed to convert. Volume limit of the pool is exceeded, the remaining volume is {}.",
+            self.max_volume.0 - self.window_volume.0
+        );
+        self.window_volume = U128(self.window_volume.0 + amount);
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolStatistics {
+    /// how many times the pool is used to convert
+    pub conversion_count: U64,
+    /// total amount of in_token converted into out_token
+    pub converted_in_token_amount: U128,
+    /// total amount of out_token converted into in_token
+    pub converted_out_token_amount: U128,
+}
+
+impl PoolStatistics {
+    pub fn new() -> Self {
+        Self {
+            conversion_count: U64(0),
+            converted_in_token_amount: U128(0),
+            converted_out_token_amount: U128(0),
+        }
+    }
+}
+
+impl Default for PoolStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// expected result of converting in a pool
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConversionQuote {
+    pub pool_id: PoolId,
+    pub output_token_id: AccountId,
+    pub expected_output_amount: U128,
+    /// balance of output token in the pool
+    pub available_output_balance: U128,
+    /// the maximum amount of input token allowed by limits of the pool, none means no limit
+    pub remaining_capacity: Option<U128>,
 }
 
 impl ConversionPool {
+    // each argument is a distinct attribute of the pool which has no default.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: PoolId,
         creator: AccountId,
@@ -71,6 +231,58 @@ impl ConversionPool {
             in_token_rate,
             out_token_rate,
             deposit_near_amount,
+            is_paused: false,
+            statistics: PoolStatistics::new(),
+            max_conversion_amount: None,
+            volume_limit: None,
+            user_quota: None,
+            is_private: false,
+            start_time: None,
+            end_time: None,
+            in_token_retirement: None,
+            pending_transfer_count: 0,
+            storage_sponsor_budget: U128(0),
+            storage_usage: U64(0),
+        }
+    }
+
+    pub fn storage_cost(&self) -> Balance {
+        self.storage_usage.0 as u128 * env::storage_byte_cost()
+    }
+
+    pub fn assert_storage_deposit(&self) {
+        assert!(
+            self.storage_cost() <= self.deposit_near_amount.0,
+            "Pool '{}' needs '{}' yocto NEAR more deposit for storage.",
+            self.id.0,
+            self.storage_cost() - self.deposit_near_amount.0
+        );
+    }
+
+    pub fn lock(&mut self) {
+        self.pending_transfer_count += 1;
+    }
+
+    pub fn unlock(&mut self) {
+        self.pending_transfer_count -= 1;
+    }
+
+    pub fn assert_not_locked(&self) {
+        assert_eq!(
+            self.pending_transfer_count, 0,
+            "Pool '{}' has transfers in processing.",
+            self.id.0
+        );
+    }
+
+    pub fn get_status(&self) -> PoolStatus {
+        let now = env::block_timestamp();
+        if self.start_time.is_some_and(|start_time| now < start_time.0) {
+            PoolStatus::Upcoming
+        } else if self.end_time.is_some_and(|end_time| now >= end_time.0) {
+            PoolStatus::Ended
+        } else {
+            PoolStatus::Active
         }
     }
 
@@ -82,7 +294,25 @@ impl ConversionPool {
         input_token_id: &AccountId,
         input_token_amount: Balance,
     ) -> (AccountId, Balance) {
+        assert!(!self.is_paused, "Pool '{}' is paused.", self.id.0);
+        assert_eq!(
+            self.get_status(),
+            PoolStatus::Active,
+            "Pool '{}' is not active.",
+            self.id.0
+        );
         self.check_input_token_legal_when_converting(input_token_id);
+        if let Some(max_conversion_amount) = self.max_conversion_amount {
+            assert!(
+                input_token_amount <= max_conversion_amount.0,
+                "Failed to convert. The amount exceeds the max conversion amount {} of the pool.",
+                max_conversion_amount.0
+            );
+        }
+        if let Some(volume_limit) = self.volume_limit.as_mut() {
+            volume_limit.record(input_token_amount);
+        }
+        self.statistics.conversion_count = U64(self.statistics.conversion_count.0 + 1);
         return if input_token_id.eq(&self.in_token) {
             let output_token_amount = self.calculate_output_token_amount(input_token_amount);
             assert!(
@@ -93,6 +323,8 @@ impl ConversionPool {
             );
             self.deposit_from_token(input_token_amount);
             self.withdraw_out_token(Option::Some(output_token_amount));
+            self.statistics.converted_in_token_amount =
+                U128(self.statistics.converted_in_token_amount.0 + input_token_amount);
             (self.out_token.clone(), output_token_amount)
         } else {
             let output_token_amount =
@@ -105,10 +337,115 @@ impl ConversionPool {
             );
             self.deposit_to_token(input_token_amount);
             self.withdraw_in_token(Option::Some(output_token_amount));
+            self.statistics.converted_out_token_amount =
+                U128(self.statistics.converted_out_token_amount.0 + input_token_amount);
             (self.in_token.clone(), output_token_amount)
         };
     }
 
+    /// whether the pool can be used to convert the input token now
+    pub fn is_convertible(&self, input_token_id: &AccountId) -> bool {
+        !self.is_paused
+            && self.get_status() == PoolStatus::Active
+            && (input_token_id.eq(&self.in_token)
+                || (input_token_id.eq(&self.out_token) && self.reversible))
+    }
+
+    /// rate of converting the input token, as (output rate, input rate)
+    pub fn get_rate(&self, input_token_id: &AccountId) -> (u32, u32) {
+        if input_token_id.eq(&self.in_token) {
+            (self.out_token_rate, self.in_token_rate)
+        } else {
+            (self.in_token_rate, self.out_token_rate)
+        }
+    }
+
+    /// the maximum amount of input token which can be converted by the balance of output token
+    pub fn get_max_input_amount(&self, input_token_id: &AccountId) -> Balance {
+        let (output_rate, input_rate) = self.get_rate(input_token_id);
+        let output_balance = if input_token_id.eq(&self.in_token) {
+            self.out_token_balance.0
+        } else {
+            self.in_token_balance.0
+        };
+        let max_input_amount =
+            U256::from(output_balance) * U256::from(input_rate) / U256::from(output_rate);
+        if max_input_amount > U256::from(Balance::MAX) {
+            Balance::MAX
+        } else {
+            max_input_amount.as_u128()
+        }
+    }
+
+    /// the maximum amount of input token allowed by limits of the pool now,
+    /// none means no limit.
+    pub fn get_remaining_capacity(&self) -> Option<Balance> {
+        let volume = self
+            .volume_limit
+            .as_ref()
+            .map(|limit| limit.get_remaining_volume());
+        match (self.max_conversion_amount.map(|e| e.0), volume) {
+            (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// the amount of input token can be converted,
+    /// it's capped by the balance of output token and limits of the pool.
+    pub fn get_fillable_input_amount(
+        &self,
+        input_token_id: &AccountId,
+        input_token_amount: Balance,
+    ) -> Balance {
+        let amount = std::cmp::min(
+            input_token_amount,
+            self.get_max_input_amount(input_token_id),
+        );
+        self.get_remaining_capacity()
+            .map(|capacity| std::cmp::min(amount, capacity))
+            .unwrap_or(amount)
+    }
+
+    /// quote converting without changing the pool,
+    /// return None if the pool can't convert the input token or its balance is not enough.
+    pub fn quote(
+        &self,
+        input_token_id: &AccountId,
+        input_token_amount: Balance,
+    ) -> Option<ConversionQuote> {
+        if !self.is_convertible(input_token_id) {
+            return None;
+        }
+        let (output_token_id, expected_output_amount, available_output_balance) =
+            if input_token_id.eq(&self.in_token) {
+                (
+                    self.out_token.clone(),
+                    self.calculate_output_token_amount(input_token_amount),
+                    self.out_token_balance.0,
+                )
+            } else {
+                (
+                    self.in_token.clone(),
+                    self.calculate_reverse_output_token_amount(input_token_amount),
+                    self.in_token_balance.0,
+                )
+            };
+        if available_output_balance < expected_output_amount {
+            return None;
+        }
+        let remaining_capacity = self.get_remaining_capacity();
+        if remaining_capacity.is_some_and(|capacity| capacity < input_token_amount) {
+            return None;
+        }
+        Some(ConversionQuote {
+            pool_id: self.id,
+            output_token_id,
+            expected_output_amount: U128(expected_output_amount),
+            available_output_balance: U128(available_output_balance),
+            remaining_capacity: remaining_capacity.map(U128),
+        })
+    }
+
     pub fn add_liquidity(&mut self, token_id: &AccountId, token_balance: Balance) {
         self.check_input_token_legal_when_adding_liquidity(token_id);
         if token_id.eq(&self.in_token) {
@@ -118,6 +455,16 @@ impl ConversionPool {
         };
     }
 
+    /// put back tokens of a failed withdrawal, which may be the in_token of a non-reversible pool,
+    /// so it's not checked like adding liquidity.
+    pub fn restore_token(&mut self, token_id: &AccountId, token_balance: Balance) {
+        if token_id.eq(&self.in_token) {
+            self.deposit_from_token(token_balance);
+        } else {
+            self.deposit_to_token(token_balance);
+        };
+    }
+
     /// calculate token amount when convert in_token into out_token
     pub fn calculate_output_token_amount(&self, token_amount: Balance) -> Balance {
         (U256::from(token_amount) * U256::from(self.out_token_rate)
@@ -229,12 +576,56 @@ impl TokenConvertor {
     pub(crate) fn internal_convert(
         &mut self,
         pool_id: PoolId,
+        account_id: &AccountId,
         input_token_id: &AccountId,
         token_amount: Balance,
     ) -> (AccountId, Balance) {
-        return self.internal_use_pool(pool_id, |pool| {
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        assert!(
+            self.internal_is_allowed_in_pool(&pool, account_id),
+            "'{}' is not allowed to convert in private pool '{}'.",
+            account_id,
+            pool_id.0
+        );
+        self.assert_token_is_not_delisted(&pool.in_token);
+        self.assert_token_is_not_delisted(&pool.out_token);
+        self.assert_token_is_not_paused(&pool.in_token);
+        self.assert_token_is_not_paused(&pool.out_token);
+        if let Some(quota) = pool.user_quota.as_ref() {
+            self.internal_use_quota(&pool_id, account_id, quota, token_amount);
+        }
+        let result = self.internal_use_pool(pool_id, |pool| {
             return pool.convert(input_token_id, token_amount);
         });
+        if let Some(InTokenRetirement::Forward { sink_id }) = pool.in_token_retirement {
+            self.internal_forward_in_token(&pool_id, &sink_id);
+        }
+        result
+    }
+
+    /// forward all of the input token in a one-way migration pool to the sink account
+    pub(crate) fn internal_forward_in_token(&mut self, pool_id: &PoolId, sink_id: &AccountId) {
+        let (in_token, amount) = self.internal_use_pool(*pool_id, |pool| {
+            let amount = pool.withdraw_in_token(None);
+            if amount > 0 {
+                pool.lock();
+            }
+            (pool.in_token.clone(), amount)
+        });
+        if amount == 0 {
+            return;
+        }
+        self.internal_decrease_liability(&in_token, amount);
+        self.internal_increase_in_flight_amount(&in_token, amount);
+        ext_ft_core::ext(in_token.clone())
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_FT_TRANSFER))
+            .ft_transfer(sink_id.clone(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_TRANSFER))
+                    .in_token_forward_resolved(*pool_id, in_token, sink_id.clone(), U128(amount)),
+            );
     }
 
     pub(crate) fn internal_assign_pool_id(&mut self) -> PoolId {
@@ -244,19 +635,44 @@ impl TokenConvertor {
 
     pub(crate) fn internal_delete_pool(&mut self, pool_id: &PoolId) {
         let pool = self
-            .internal_get_pool(&pool_id)
-            .expect(format!("Pool '{}' is not existed.", pool_id.0).as_str());
-        assert_eq!(
-            pool.in_token_balance.0, 0,
-            "Failed to delete pool '{}'. All of the 'in token' in the pool must be withdrawn first.",
-            pool_id.0
-        );
+            .internal_get_pool(pool_id)
+            .unwrap_or_else(|| panic!("Pool '{}' is not existed.", pool_id.0));
+        pool.assert_not_locked();
+        // the 'in token' locked by a one-way migration pool can't be withdrawn,
+        // it stays in the contract as a liability after the pool is deleted.
+        if pool.in_token_retirement == Some(InTokenRetirement::Lock) {
+            if pool.in_token_balance.0 > 0 {
+                log!(
+                    "{} of token '{}' locked by pool '{}' stays in the contract.",
+                    pool.in_token_balance.0,
+                    pool.in_token,
+                    pool_id.0
+                );
+            }
+        } else {
+            assert_eq!(
+                pool.in_token_balance.0, 0,
+                "Failed to delete pool '{}'. All of the 'in token' in the pool must be withdrawn first.",
+                pool_id.0
+            );
+        }
         assert_eq!(
             pool.out_token_balance.0, 0,
             "Failed to delete pool '{}'. All of the 'out token' in the pool must be withdrawn first.",
             pool_id.0
         );
-        self.pools.remove(pool_id);
+        // the allowlist may be too large to remove at once, so it's emptied in batches beforehand.
+        assert!(
+            self.pool_allowlists.get(pool_id).is_none(),
+            "Failed to delete pool '{}'. All accounts in the allowlist of the pool must be removed first.",
+            pool_id.0
+        );
+        if let Some(VPool::V1(_)) = self.pools.remove(pool_id) {
+            self.legacy_pool_count -= 1;
+        }
+        self.pool_index.remove_pool(&pool);
+        self.internal_clean_delisted_token(&pool.in_token);
+        self.internal_clean_delisted_token(&pool.out_token);
         log!(
             "Pool '{}' is deleted by '{}'.",
             pool_id.0,
@@ -270,7 +686,7 @@ impl TokenConvertor {
     {
         let mut pool = self.internal_get_pool(&pool_id).expect("No such pool.");
         let r = f(&mut pool);
-        self.internal_save_pool(pool_id, &pool.into());
+        self.internal_save_pool(pool_id, pool);
         r
     }
 
@@ -279,9 +695,206 @@ impl TokenConvertor {
         return self.pools.get(pool_id).map(|pool| pool.into_current());
     }
 
-    pub(crate) fn internal_save_pool(&mut self, pool_id: PoolId, pool: &VPool) {
-        self.pools.insert(&pool_id, &pool);
-        // self.pools.replace(pool_id, &pool);
+    /// whether the account can convert the input token in the pool now, it's checked like `internal_convert`.
+    /// private pools are excluded if the account is not given.
+    fn internal_can_convert_in_pool(
+        &self,
+        pool: &ConversionPool,
+        input_token: &AccountId,
+        account_id: Option<&AccountId>,
+    ) -> bool {
+        pool.is_convertible(input_token)
+            && account_id.map_or(!pool.is_private, |account_id| {
+                self.internal_is_allowed_in_pool(pool, account_id)
+            })
+            && [&pool.in_token, &pool.out_token].iter().all(|token| {
+                self.delisted_tokens.get(token).is_none() && !self.paused_tokens.contains(token)
+            })
+    }
+
+    /// the amount of input token the account can still convert in the pool by user quota,
+    /// it's the max amount of the quota if the account is not given. none means the pool has no quota.
+    fn internal_get_quota_capacity(
+        &self,
+        pool: &ConversionPool,
+        account_id: Option<&AccountId>,
+    ) -> Option<Balance> {
+        match account_id {
+            Some(account_id) => self.internal_get_remaining_quota(&pool.id, account_id),
+            None => pool.user_quota.as_ref().map(|quota| quota.max_amount.0),
+        }
+    }
+
+    /// quotes of all pools which can convert input token into output token for the account,
+    /// sorted by expected output amount from high to low.
+    pub(crate) fn internal_quote_pools(
+        &self,
+        input_token: &AccountId,
+        output_token: &AccountId,
+        amount: Balance,
+        account_id: Option<&AccountId>,
+    ) -> Vec<ConversionQuote> {
+        let mut pool_ids = self
+            .pool_index
+            .get_pool_ids_by_pair(input_token, output_token);
+        // reversible pools created for the opposite direction can also be used.
+        pool_ids.extend(
+            self.pool_index
+                .get_pool_ids_by_pair(output_token, input_token),
+        );
+        self.internal_get_pools_by_ids(pool_ids)
+            .iter()
+            .filter(|pool| self.internal_can_convert_in_pool(pool, input_token, account_id))
+            .filter_map(|pool| {
+                let mut quote = pool.quote(input_token, amount)?;
+                if let Some(quota_capacity) = self.internal_get_quota_capacity(pool, account_id) {
+                    if quota_capacity < amount {
+                        return None;
+                    }
+                    quote.remaining_capacity = Some(U128(
+                        quote
+                            .remaining_capacity
+                            .map_or(quota_capacity, |capacity| capacity.0.min(quota_capacity)),
+                    ));
+                }
+                Some(quote)
+            })
+            .sorted_by(|a, b| {
+                b.expected_output_amount
+                    .0
+                    .cmp(&a.expected_output_amount.0)
+                    .then(
+                        b.available_output_balance
+                            .0
+                            .cmp(&a.available_output_balance.0),
+                    )
+            })
+            .collect_vec()
+    }
+
+    /// split the amount into pools which can convert input token into output token for the account,
+    /// pools with better rate are filled first, each within its balance, limits and the account's quota.
+    pub(crate) fn internal_plan_split_conversion(
+        &self,
+        input_token: &AccountId,
+        output_token: &AccountId,
+        amount: Balance,
+        account_id: &AccountId,
+    ) -> Vec<(PoolId, Balance)> {
+        let mut pool_ids = self
+            .pool_index
+            .get_pool_ids_by_pair(input_token, output_token);
+        pool_ids.extend(
+            self.pool_index
+                .get_pool_ids_by_pair(output_token, input_token),
+        );
+        let pools = self
+            .internal_get_pools_by_ids(pool_ids)
+            .into_iter()
+            .filter(|pool| self.internal_can_convert_in_pool(pool, input_token, Some(account_id)))
+            .sorted_by(|a, b| {
+                let (a_output_rate, a_input_rate) = a.get_rate(input_token);
+                let (b_output_rate, b_input_rate) = b.get_rate(input_token);
+                (b_output_rate as u64 * a_input_rate as u64)
+                    .cmp(&(a_output_rate as u64 * b_input_rate as u64))
+            })
+            .collect_vec();
+        let mut legs = vec![];
+        let mut remaining_amount = amount;
+        for pool in pools {
+            if remaining_amount == 0 {
+                break;
+            }
+            let leg_amount = pool.get_fillable_input_amount(input_token, remaining_amount);
+            let leg_amount = self
+                .internal_get_quota_capacity(&pool, Some(account_id))
+                .map_or(leg_amount, |quota_capacity| leg_amount.min(quota_capacity));
+            if leg_amount > 0 {
+                legs.push((pool.id, leg_amount));
+                remaining_amount -= leg_amount;
+            }
+        }
+        assert_eq!(
+            remaining_amount, 0,
+            "Failed to convert. The balance of pools is not enough or their limits are reached, '{}' of input token can't be converted.",
+            remaining_amount
+        );
+        legs
+    }
+
+    pub(crate) fn internal_get_pools_by_ids(&self, pool_ids: Vec<PoolId>) -> Vec<ConversionPool> {
+        pool_ids
+            .iter()
+            .map(|pool_id| self.internal_get_pool(pool_id).expect("No such pool."))
+            .collect_vec()
+    }
+
+    /// write the pool and measure the storage it uses by env::storage_usage,
+    /// the deposit of pool should pay the storage if it grows.
+    pub(crate) fn internal_save_pool(&mut self, pool_id: PoolId, mut pool: ConversionPool) {
+        let old_pool = self.pools.get(&pool_id);
+        let initial_storage_usage = env::storage_usage();
+        self.pools.insert(&pool_id, &pool.clone().into());
+        let storage_usage = env::storage_usage();
+        pool.storage_usage =
+            U64((pool.storage_usage.0 + storage_usage).saturating_sub(initial_storage_usage));
+        if let Some(VPool::V1(old_pool)) = old_pool {
+            // indexes and liabilities of pools in the released version are built when migrated,
+            // and they are not charged for storage.
+            self.pool_index.add_pool(&pool);
+            self.internal_increase_liability(&old_pool.in_token, old_pool.in_token_balance.0);
+            self.internal_increase_liability(&old_pool.out_token, old_pool.out_token_balance.0);
+            self.legacy_pool_count -= 1;
+        } else if storage_usage > initial_storage_usage {
+            pool.assert_storage_deposit();
+        }
+        // the size of storage_usage is fixed, so the record is rewritten in place.
+        self.pools.insert(&pool_id, &pool.into());
+    }
+
+    /// add the storage used by collections of the pool since initial_storage_usage,
+    /// which is paid by the deposit of pool.
+    pub(crate) fn internal_charge_pool_storage(
+        &mut self,
+        pool_id: &PoolId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        let storage_usage = env::storage_usage();
+        self.internal_use_pool(*pool_id, |pool| {
+            pool.storage_usage =
+                U64((pool.storage_usage.0 + storage_usage).saturating_sub(initial_storage_usage));
+            if storage_usage > initial_storage_usage {
+                pool.assert_storage_deposit();
+            }
+        });
+    }
+
+    pub(crate) fn internal_assert_pool_manager(&self, pool: &ConversionPool) {
+        assert!(
+            pool.creator.eq(&env::predecessor_account_id())
+                || self.owner.eq(&env::predecessor_account_id()),
+            "Only contract owner or pool creator can manage the pool."
+        );
+    }
+}
+
+#[near_bindgen]
+impl TokenConvertor {
+    /// rewrite pools of old versions into the current version and build their indexes and liabilities,
+    /// anyone can call it to migrate pools eagerly instead of waiting for them to be used.
+    /// return the number of migrated pools.
+    pub fn migrate_pools(&mut self, from_index: u32, limit: u32) -> u32 {
+        let outdated_pools = self
+            .pools
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|(_, pool)| !pool.is_current())
+            .collect_vec();
+        for (pool_id, pool) in outdated_pools.iter() {
+            self.internal_save_pool(*pool_id, pool.clone().into_current());
+        }
+        outdated_pools.len() as u32
     }
 }
 
@@ -295,13 +908,23 @@ impl PoolCreatorAction for TokenConvertor {
         is_reversible: bool,
         in_token_rate: u32,
         out_token_rate: u32,
+        time_window: Option<TimeWindow>,
     ) -> PoolId {
         self.assert_contract_is_not_paused();
+        let (start_time, end_time) = time_window
+            .map(|window| (window.start_time, window.end_time))
+            .unwrap_or_default();
+        if let Some(end_time) = end_time {
+            assert!(
+                end_time.0 > env::block_timestamp()
+                    && end_time.0 > start_time.map_or(0, |start_time| start_time.0),
+                "End time should be later than start time and now."
+            );
+        }
         assert!(
             !in_token.eq(&out_token),
             "Can not create pool for two same tokens."
         );
-        self.assert_create_pool_deposit_amount();
         self.assert_token_in_whitelist(&in_token);
         self.assert_token_in_whitelist(&out_token);
         assert_eq!(
@@ -310,23 +933,35 @@ impl PoolCreatorAction for TokenConvertor {
             "Tokens in a pool should have the same decimals."
         );
         let id = self.internal_assign_pool_id();
-        self.pools.insert(
-            &id,
-            &VPool::Current(ConversionPool::new(
-                id.clone(),
-                env::predecessor_account_id(),
-                in_token.clone(),
-                out_token.clone(),
-                is_reversible,
-                in_token_rate,
-                out_token_rate,
-                U128(env::attached_deposit()),
-            )),
+        let mut pool = ConversionPool::new(
+            id,
+            env::predecessor_account_id(),
+            in_token.clone(),
+            out_token.clone(),
+            is_reversible,
+            in_token_rate,
+            out_token_rate,
+            U128(0),
         );
-        PoolEvent::CreatePool {
-            pool: self.internal_get_pool(&id).as_ref().unwrap(),
+        pool.start_time = start_time;
+        pool.end_time = end_time;
+        let initial_storage_usage = env::storage_usage();
+        self.pools.insert(&id, &pool.clone().into());
+        self.pool_index.add_pool(&pool);
+        pool.storage_usage = U64(env::storage_usage() - initial_storage_usage);
+        let deposit_amount = std::cmp::max(pool.storage_cost(), self.create_pool_deposit);
+        assert!(
+            env::attached_deposit() >= deposit_amount,
+            "Creating a pool requires a deposit of '{}' yocto NEAR.",
+            deposit_amount
+        );
+        pool.deposit_near_amount = U128(deposit_amount);
+        self.pools.insert(&id, &pool.clone().into());
+        PoolEvent::CreatePool { pool: &pool }.emit();
+        let refund_amount = env::attached_deposit() - deposit_amount;
+        if refund_amount > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund_amount);
         }
-        .emit();
         id
     }
 
@@ -353,14 +988,20 @@ impl PoolCreatorAction for TokenConvertor {
                 pool.in_token,
                 pool.out_token
             );
-            return (
-                pool.creator.clone(),
-                if token_id == pool.in_token {
-                    pool.withdraw_in_token(amount.map(|e| e.0))
-                } else {
-                    pool.withdraw_out_token(amount.map(|e| e.0))
-                },
+            assert!(
+                token_id != pool.in_token || pool.in_token_retirement.is_none(),
+                "Input token of a one-way migration pool can't be withdrawn."
             );
+            pool.assert_not_locked();
+            let withdraw_amount = if token_id == pool.in_token {
+                pool.withdraw_in_token(amount.map(|e| e.0))
+            } else {
+                pool.withdraw_out_token(amount.map(|e| e.0))
+            };
+            if withdraw_amount > 0 {
+                pool.lock();
+            }
+            (pool.creator.clone(), withdraw_amount)
         });
         PoolEvent::UpdatePool {
             pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
@@ -368,8 +1009,108 @@ impl PoolCreatorAction for TokenConvertor {
         .emit();
         // pool should finish withdraw here
         if withdraw_amount > 0 {
-            self.internal_send_tokens(&creator, &token_id, withdraw_amount);
+            self.internal_send_tokens(
+                &creator,
+                &token_id,
+                withdraw_amount,
+                TransferOrigin::PoolWithdrawal { pool_id },
+            );
+        }
+    }
+
+    fn pause_pool(&mut self, pool_id: PoolId) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        self.internal_use_pool(pool_id, |pool| {
+            assert!(!pool.is_paused, "Pool is already paused.");
+            pool.is_paused = true;
+        });
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+    }
+
+    fn resume_pool(&mut self, pool_id: PoolId) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        self.internal_use_pool(pool_id, |pool| {
+            assert!(pool.is_paused, "Pool is already active.");
+            pool.is_paused = false;
+        });
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+    }
+
+    fn set_pool_limits(
+        &mut self,
+        pool_id: PoolId,
+        max_conversion_amount: Option<U128>,
+        volume_limit: Option<(U64, U128)>,
+    ) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        self.internal_use_pool(pool_id, |pool| {
+            pool.max_conversion_amount = max_conversion_amount;
+            // the volume converted in the current window is kept when the limit is changed.
+            let old_limit = pool.volume_limit.take();
+            pool.volume_limit = volume_limit.map(|(window_duration, max_volume)| {
+                let mut limit = VolumeLimit::new(window_duration, max_volume);
+                if let Some(old_limit) = old_limit.as_ref() {
+                    limit.window_start = old_limit.window_start;
+                    limit.window_volume = old_limit.window_volume;
+                }
+                limit
+            });
+        });
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+    }
+
+    fn set_pool_user_quota(&mut self, pool_id: PoolId, user_quota: Option<(U64, U128)>) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        self.internal_use_pool(pool_id, |pool| {
+            pool.user_quota = user_quota
+                .map(|(window_duration, max_amount)| UserQuota::new(window_duration, max_amount));
+        });
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+    }
+
+    fn set_pool_in_token_retirement(&mut self, pool_id: PoolId, retirement: InTokenRetirement) {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        assert!(
+            !pool.reversible,
+            "A reversible pool can't be a one-way migration pool."
+        );
+        assert!(
+            pool.in_token_retirement.is_none(),
+            "Retirement of input token in pool '{}' can't be changed.",
+            pool_id.0
+        );
+        self.internal_use_pool(pool_id, |pool| {
+            pool.in_token_retirement = Some(retirement.clone())
+        });
+        if let InTokenRetirement::Forward { sink_id } = retirement {
+            self.internal_forward_in_token(&pool_id, &sink_id);
         }
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
     }
 
     #[payable]
@@ -384,8 +1125,600 @@ impl PoolCreatorAction for TokenConvertor {
         );
         self.internal_delete_pool(&pool_id);
         PoolEvent::DeletePool { pool_id: &pool_id }.emit();
-        if pool.deposit_near_amount.0 > 0 {
-            self.internal_send_near(pool.creator.clone(), pool.deposit_near_amount.0);
+        let refund_amount = pool.deposit_near_amount.0 + pool.storage_sponsor_budget.0;
+        if refund_amount > 0 {
+            self.internal_send_near(pool.creator.clone(), refund_amount);
+        }
+    }
+
+    #[payable]
+    fn deposit_pool_storage(&mut self, pool_id: PoolId) -> U128 {
+        self.assert_contract_is_not_paused();
+        let pool = self.internal_get_pool(&pool_id).expect("No such pool.");
+        self.internal_assert_pool_manager(&pool);
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Deposit should be greater than 0.");
+        let deposit_near_amount = self.internal_use_pool(pool_id, |pool| {
+            pool.deposit_near_amount = U128(pool.deposit_near_amount.0 + amount);
+            pool.deposit_near_amount
+        });
+        PoolEvent::UpdatePool {
+            pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
+        }
+        .emit();
+        deposit_near_amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::contract_interfaces::ConvertorViewer;
+    use crate::contract_interfaces::PoolAllowlistAction;
+    use crate::test::{setup_contract, usdc, usdt, whitelist_tokens};
+    use crate::token_receiver::{ConvertAction, TransferMessage};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    fn pool_v1(id: u64) -> ConversionPoolV1 {
+        ConversionPoolV1 {
+            id: U64(id),
+            creator: bob(),
+            in_token: usdc(),
+            in_token_balance: U128(10),
+            out_token: usdt(),
+            out_token_balance: U128(20),
+            reversible: true,
+            in_token_rate: 1,
+            out_token_rate: 1,
+            deposit_near_amount: U128(1),
+        }
+    }
+
+    #[test]
+    fn test_lazy_migrate_pool() {
+        let (_, mut contract, _) = setup_contract();
+        contract.pools.insert(&U64(1), &VPool::V1(pool_v1(1)));
+        contract.legacy_pool_count = 1;
+
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.in_token_balance, U128(10));
+        assert!(!pool.is_paused);
+        assert!(!contract.pools.get(&U64(1)).unwrap().is_current());
+
+        contract.internal_convert(U64(1), &bob(), &usdc(), 5);
+        let pool = contract.pools.get(&U64(1)).unwrap();
+        assert!(pool.is_current());
+        assert_eq!(contract.legacy_pool_count, 0);
+        // liabilities of the pool are counted before the conversion.
+        assert_eq!(contract.get_token_liabilities(usdc()), U128(10));
+        assert_eq!(contract.get_pools_by_token(usdc(), 0, 10).len(), 1);
+        assert_eq!(
+            pool.into_current().statistics.converted_in_token_amount,
+            U128(5)
+        );
+    }
+
+    #[test]
+    fn test_migrate_pools() {
+        let (_, mut contract, _) = setup_contract();
+        for id in 1..=3 {
+            contract.pools.insert(&U64(id), &VPool::V1(pool_v1(id)));
         }
+        contract.legacy_pool_count = 3;
+        assert_eq!(contract.migrate_pools(0, 2), 2);
+        assert!(contract.pools.get(&U64(2)).unwrap().is_current());
+        assert!(!contract.pools.get(&U64(3)).unwrap().is_current());
+        assert_eq!(contract.migrate_pools(0, 10), 1);
+        assert_eq!(contract.migrate_pools(0, 10), 0);
+        assert_eq!(contract.legacy_pool_count, 0);
+        assert_eq!(contract.get_token_liabilities(usdt()), U128(60));
+        assert_eq!(contract.get_pools_by_creator(bob(), 0, 10).len(), 3);
+    }
+
+    /// pools are specified as (in_token, out_token, reversible, in_token_rate, out_token_rate, balance)
+    fn add_pools(
+        contract: &mut TokenConvertor,
+        pools: Vec<(AccountId, AccountId, bool, u32, u32, Balance)>,
+    ) {
+        for (in_token, out_token, reversible, in_token_rate, out_token_rate, balance) in pools {
+            let id = contract.internal_assign_pool_id();
+            let mut pool = ConversionPool::new(
+                id,
+                bob(),
+                in_token,
+                out_token,
+                reversible,
+                in_token_rate,
+                out_token_rate,
+                U128(10u128.pow(24)),
+            );
+            pool.in_token_balance = U128(balance);
+            pool.out_token_balance = U128(balance);
+            contract.internal_increase_liability(&pool.in_token, balance);
+            contract.internal_increase_liability(&pool.out_token, balance);
+            contract.pools.insert(&id, &pool.clone().into());
+            contract.pool_index.add_pool(&pool);
+        }
+    }
+
+    #[test]
+    fn test_find_best_pool() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(
+            &mut contract,
+            vec![
+                (usdc(), usdt(), false, 1, 1, 100),
+                (usdc(), usdt(), false, 10, 11, 100),
+                (usdc(), usdt(), false, 10, 12, 1),
+                (usdt(), usdc(), true, 10, 9, 0),
+                (usdt(), usdc(), false, 1, 2, 0),
+            ],
+        );
+
+        let quotes = contract.internal_quote_pools(&usdc(), &usdt(), 10, None);
+        assert_eq!(
+            quotes.iter().map(|e| e.pool_id.0).collect_vec(),
+            vec![2, 1],
+            "pool 3 lacks balance, pool 4 has no balance, pool 5 is not reversible"
+        );
+        assert_eq!(quotes[0].expected_output_amount, U128(11));
+        assert_eq!(quotes[1].expected_output_amount, U128(10));
+    }
+
+    #[test]
+    fn test_plan_split_conversion() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(
+            &mut contract,
+            vec![
+                (usdc(), usdt(), false, 1, 1, 100),
+                (usdc(), usdt(), false, 1, 2, 20),
+                (usdt(), usdc(), true, 1, 1, 30),
+                (usdt(), usdc(), false, 1, 3, 100),
+            ],
+        );
+        assert_eq!(
+            contract.internal_plan_split_conversion(&usdc(), &usdt(), 50, &bob()),
+            vec![(U64(2), 10), (U64(1), 40)]
+        );
+        assert_eq!(
+            contract.internal_plan_split_conversion(&usdc(), &usdt(), 140, &bob()),
+            vec![(U64(2), 10), (U64(1), 100), (U64(3), 30)]
+        );
+    }
+
+    #[test]
+    fn test_quote_and_plan_with_pool_limits() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(
+            &mut contract,
+            vec![
+                (usdc(), usdt(), false, 1, 1, 100),
+                (usdc(), usdt(), false, 1, 2, 100),
+                (usdc(), usdt(), false, 1, 3, 100),
+            ],
+        );
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.user_quota = Some(UserQuota::new(U64(1000), U128(30)))
+        });
+        contract.internal_use_pool(U64(2), |pool| pool.max_conversion_amount = Some(U128(10)));
+        contract.internal_use_pool(U64(3), |pool| pool.is_private = true);
+
+        let quotes = contract.internal_quote_pools(&usdc(), &usdt(), 20, Some(&bob()));
+        assert_eq!(
+            quotes.iter().map(|e| e.pool_id.0).collect_vec(),
+            vec![1],
+            "pool 2 exceeds max conversion amount, bob is not allowed in pool 3"
+        );
+        assert_eq!(quotes[0].remaining_capacity, Some(U128(30)));
+        assert_eq!(
+            contract.internal_plan_split_conversion(&usdc(), &usdt(), 35, &bob()),
+            vec![(U64(2), 10), (U64(1), 25)]
+        );
+
+        contract.paused_tokens.insert(&usdt());
+        assert!(contract
+            .internal_quote_pools(&usdc(), &usdt(), 10, Some(&bob()))
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "'1' of input token can't be converted")]
+    fn test_plan_split_conversion_beyond_quota() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.user_quota = Some(UserQuota::new(U64(1000), U128(30)))
+        });
+        contract.internal_plan_split_conversion(&usdc(), &usdt(), 31, &bob());
+    }
+
+    #[test]
+    #[should_panic(expected = "The balance of pools is not enough")]
+    fn test_plan_split_conversion_insufficient_balance() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        contract.internal_plan_split_conversion(&usdc(), &usdt(), 101, &bob());
+    }
+
+    #[test]
+    fn test_pool_limits() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.max_conversion_amount = Some(U128(20));
+            pool.volume_limit = Some(VolumeLimit::new(U64(1000), U128(30)));
+        });
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_remaining_capacity(), Some(20));
+        assert_eq!(pool.get_fillable_input_amount(&usdc(), 50), 20);
+
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_remaining_capacity(), Some(10));
+        assert_eq!(
+            contract.internal_quote_pools(&usdc(), &usdt(), 10, None)[0].remaining_capacity,
+            Some(U128(10))
+        );
+        assert!(contract
+            .internal_quote_pools(&usdc(), &usdt(), 11, None)
+            .is_empty());
+
+        testing_env!(context
+            .block_timestamp(env::block_timestamp() + 1000)
+            .build());
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_remaining_capacity(), Some(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "Volume limit of the pool is exceeded")]
+    fn test_set_pool_limits_keeps_window_volume() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.volume_limit = Some(VolumeLimit::new(U64(1000), U128(30)));
+        });
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_limits(U64(1), None, Some((U64(1000), U128(40))));
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_remaining_capacity(), Some(20));
+        contract.internal_convert(U64(1), &bob(), &usdc(), 21);
+    }
+
+    #[test]
+    #[should_panic(expected = "Volume limit of the pool is exceeded")]
+    fn test_pool_volume_limit_exceeded() {
+        let (_, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.volume_limit = Some(VolumeLimit::new(U64(1000), U128(30)));
+        });
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 11);
+    }
+
+    #[test]
+    fn test_pool_time_window() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        let now = env::block_timestamp();
+        contract.internal_use_pool(U64(1), |pool| {
+            pool.start_time = Some(U64(now + 100));
+            pool.end_time = Some(U64(now + 200));
+        });
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_status(), PoolStatus::Upcoming);
+        assert!(contract
+            .internal_quote_pools(&usdc(), &usdt(), 10, None)
+            .is_empty());
+
+        testing_env!(context.block_timestamp(now + 100).build());
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_status(), PoolStatus::Active);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 10);
+
+        testing_env!(context.block_timestamp(now + 200).build());
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.get_status(), PoolStatus::Ended);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool '1' is not active.")]
+    fn test_convert_in_ended_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        let now = env::block_timestamp();
+        contract.internal_use_pool(U64(1), |pool| pool.end_time = Some(U64(now + 100)));
+        testing_env!(context.block_timestamp(now + 100).build());
+        contract.internal_convert(U64(1), &bob(), &usdc(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Input token of a one-way migration pool can't be withdrawn.")]
+    fn test_withdraw_locked_in_token() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_in_token_retirement(U64(1), InTokenRetirement::Lock);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 10);
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.in_token_balance, U128(110));
+        contract.withdraw_token_in_pool(U64(1), usdc(), None);
+    }
+
+    #[test]
+    fn test_forward_in_token() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&alice(), account);
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 0)]);
+        contract.internal_use_pool(U64(1), |pool| pool.out_token_balance = U128(100));
+        contract.internal_increase_liability(&usdt(), 100);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        let sink_id = AccountId::new_unchecked("sink.near".to_string());
+        contract.set_pool_in_token_retirement(
+            U64(1),
+            InTokenRetirement::Forward {
+                sink_id: sink_id.clone(),
+            },
+        );
+
+        testing_env!(context.predecessor_account_id(usdc()).build());
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id: U64(1),
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: false,
+                deadline: None,
+            },
+        })
+        .unwrap();
+        contract.ft_on_transfer(alice(), U128(10), msg);
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.in_token_balance, U128(0));
+        assert_eq!(pool.out_token_balance, U128(90));
+        assert_eq!(contract.internal_get_liability(&usdc()), 0);
+        assert_eq!(contract.internal_get_in_flight_amount(&usdc()), 10);
+
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.in_token_forward_resolved(U64(1), usdc(), sink_id, U128(10));
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.in_token_balance, U128(10));
+        assert_eq!(contract.internal_get_liability(&usdc()), 10);
+        assert_eq!(contract.internal_get_in_flight_amount(&usdc()), 0);
+    }
+
+    #[test]
+    fn test_pool_storage_deposit() {
+        let (mut context, mut contract, _) = setup_contract();
+        whitelist_tokens(&mut contract);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let pool_id = contract.create_pool(usdc(), usdt(), false, 1, 1, None);
+        let pool = contract.internal_get_pool(&pool_id).unwrap();
+        assert!(pool.storage_usage.0 > 0);
+        assert_eq!(pool.deposit_near_amount.0, pool.storage_cost());
+        // the overpayment is refunded.
+        assert_eq!(get_created_receipts().len(), 1);
+
+        let accounts = (0..10)
+            .map(|i| AccountId::new_unchecked(format!("account{}.near", i)))
+            .collect_vec();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10u128.pow(23))
+            .build());
+        assert_eq!(
+            contract.deposit_pool_storage(pool_id).0,
+            pool.deposit_near_amount.0 + 10u128.pow(23)
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.add_accounts_to_pool_allowlist(pool_id, accounts);
+        let pool = contract.internal_get_pool(&pool_id).unwrap();
+        assert!(pool.storage_cost() <= pool.deposit_near_amount.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool '1' needs '")]
+    fn test_pool_allowlist_storage_not_paid() {
+        let (mut context, mut contract, _) = setup_contract();
+        whitelist_tokens(&mut contract);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let pool_id = contract.create_pool(usdc(), usdt(), false, 1, 1, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.add_accounts_to_pool_allowlist(pool_id, vec![alice()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "A reversible pool can't be a one-way migration pool.")]
+    fn test_reversible_migration_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), true, 1, 1, 100)]);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_in_token_retirement(U64(1), InTokenRetirement::Lock);
+    }
+
+    #[test]
+    fn test_delete_lock_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        register_creator(&mut contract);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        contract.set_pool_in_token_retirement(U64(1), InTokenRetirement::Lock);
+        contract.internal_increase_liability(&usdc(), 10);
+        contract.internal_convert(U64(1), &alice(), &usdc(), 10);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdt(), None);
+        resolve_with(&mut context, PromiseResult::Successful(vec![]));
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(90),
+            Some(U64(0)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.delete_pool(U64(1));
+        assert!(contract.internal_get_pool(&U64(1)).is_none());
+        assert_eq!(contract.internal_get_liability(&usdc()), 110);
+        // the deposit of the pool is refunded to the creator.
+        assert_eq!(get_created_receipts().len(), 1);
+    }
+
+    fn register_creator(contract: &mut TokenConvertor) {
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+    }
+
+    fn resolve_with(context: &mut VMContextBuilder, result: PromiseResult) {
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn test_pool_lock_with_interleaved_callbacks() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        register_creator(&mut contract);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdt(), Some(U128(30)));
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.pending_transfer_count, 1);
+
+        // conversions are not blocked by the pending withdrawal.
+        contract.internal_increase_liability(&usdc(), 10);
+        contract.internal_convert(U64(1), &alice(), &usdc(), 10);
+
+        resolve_with(&mut context, PromiseResult::Successful(vec![]));
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(30),
+            Some(U64(0)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.pending_transfer_count, 0);
+        assert_eq!(pool.out_token_balance, U128(60));
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdt(), None);
+        resolve_with(&mut context, PromiseResult::Successful(vec![]));
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(60),
+            Some(U64(1)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdc(), None);
+        resolve_with(&mut context, PromiseResult::Successful(vec![]));
+        contract.ft_transfer_resolved(
+            usdc(),
+            bob(),
+            U128(110),
+            Some(U64(2)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.delete_pool(U64(1));
+        assert!(contract.internal_get_pool(&U64(1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool '1' has transfers in processing.")]
+    fn test_withdraw_locked_pool_twice() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 100)]);
+        register_creator(&mut contract);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdt(), Some(U128(30)));
+        contract.withdraw_token_in_pool(U64(1), usdt(), Some(U128(30)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool '1' has transfers in processing.")]
+    fn test_delete_pool_with_pending_transfer() {
+        let (mut context, mut contract, _) = setup_contract();
+        add_pools(&mut contract, vec![(usdc(), usdt(), false, 1, 1, 0)]);
+        testing_env!(context.predecessor_account_id(bob()).build());
+        let sink_id = AccountId::new_unchecked("sink.near".to_string());
+        contract.set_pool_in_token_retirement(U64(1), InTokenRetirement::Forward { sink_id });
+        contract.internal_use_pool(U64(1), |pool| pool.out_token_balance = U128(10));
+        contract.internal_increase_liability(&usdt(), 10);
+        contract.internal_increase_liability(&usdc(), 10);
+        contract.internal_convert(U64(1), &alice(), &usdc(), 10);
+        assert_eq!(
+            contract
+                .internal_get_pool(&U64(1))
+                .unwrap()
+                .pending_transfer_count,
+            1
+        );
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.delete_pool(U64(1));
     }
 }
@@ -1,9 +1,11 @@
 use crate::conversion_pool::ConversionPool;
 use crate::serde_json::Value;
 use crate::PoolId;
+use near_sdk::json_types::U128;
 use near_sdk::log;
 use near_sdk::serde::Serialize;
 use near_sdk::serde_json::json;
+use near_sdk::AccountId;
 
 pub const EVENT_STANDARD: &str = "convertor";
 pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
@@ -13,9 +15,76 @@ pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
 #[serde(tag = "pool_event", content = "data")]
 #[serde(rename_all = "snake_case")]
 pub enum PoolEvent<'a> {
-    CreatePool { pool: &'a ConversionPool },
-    UpdatePool { pool: &'a ConversionPool },
-    DeletePool { pool_id: &'a PoolId },
+    CreatePool {
+        pool: &'a ConversionPool,
+    },
+    UpdatePool {
+        pool: &'a ConversionPool,
+    },
+    DeletePool {
+        pool_id: &'a PoolId,
+    },
+    Convert {
+        pool_id: &'a PoolId,
+        account_id: &'a AccountId,
+        input_token_id: &'a AccountId,
+        /// amount of input token converted by the pool
+        filled_amount: &'a U128,
+        /// amount of input token refunded to the account
+        refunded_amount: &'a U128,
+        output_token_id: &'a AccountId,
+        output_amount: &'a U128,
+    },
+}
+
+/// steps of unregistering an account
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "account_event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountEvent<'a> {
+    /// the account is unregistered by force, its remaining tokens are swept
+    StartUnregister { account_id: &'a AccountId },
+    /// a remaining token balance is sent to the account
+    SweepToken {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: &'a U128,
+    },
+    /// a remaining token balance which can't be sent is recorded as lost and found of the account
+    SweepTokenToLostFound {
+        account_id: &'a AccountId,
+        token_id: &'a AccountId,
+        amount: &'a U128,
+    },
+    /// the account is deleted and its storage deposit is refunded
+    Unregister {
+        account_id: &'a AccountId,
+        refunded_amount: &'a U128,
+    },
+}
+
+/// result of comparing the balance held by the contract with its liabilities of a token
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReconcileEvent {
+    pub token_id: AccountId,
+    /// balance of the contract returned by `ft_balance_of`
+    pub balance: U128,
+    /// total balances of the token in pools and accounts
+    pub liabilities: U128,
+    /// amount of the token being transferred out of the contract
+    pub in_flight_amount: U128,
+    /// the token is paused if balance is less than liabilities
+    pub is_under_collateralized: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "token_event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEvent<'a> {
+    Reconcile(&'a ReconcileEvent),
 }
 
 pub trait EventEmit {
@@ -29,6 +98,10 @@ pub trait EventEmit {
 
 impl EventEmit for PoolEvent<'_> {}
 
+impl EventEmit for TokenEvent<'_> {}
+
+impl EventEmit for AccountEvent<'_> {}
+
 // Emit event that follows NEP-297 standard: https://nomicon.io/Standards/EventsFormat
 // Arguments
 // * `standard`: name of standard, e.g. nep171
@@ -53,8 +126,8 @@ pub(crate) fn emit_event<T: ?Sized + Serialize>(data: &T) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::conversion_pool::PoolStatistics;
     use crate::test::{usdc, usdt};
-    use near_sdk::json_types::U128;
     use near_sdk::json_types::U64;
     use near_sdk::test_utils;
     use near_sdk::test_utils::test_env::bob;
@@ -73,6 +146,18 @@ mod tests {
                 in_token_rate: 0,
                 out_token_rate: 0,
                 deposit_near_amount: U128(1),
+                is_paused: false,
+                statistics: PoolStatistics::new(),
+                max_conversion_amount: None,
+                volume_limit: None,
+                user_quota: None,
+                is_private: false,
+                start_time: None,
+                end_time: None,
+                in_token_retirement: None,
+                pending_transfer_count: 0,
+                storage_sponsor_budget: U128(0),
+                storage_usage: U64(0),
             },
         }
         .emit();
@@ -89,23 +174,50 @@ mod tests {
                 in_token_rate: 0,
                 out_token_rate: 0,
                 deposit_near_amount: U128(1),
+                is_paused: false,
+                statistics: PoolStatistics::new(),
+                max_conversion_amount: None,
+                volume_limit: None,
+                user_quota: None,
+                is_private: false,
+                start_time: None,
+                end_time: None,
+                in_token_retirement: None,
+                pending_transfer_count: 0,
+                storage_sponsor_budget: U128(0),
+                storage_usage: U64(0),
             },
         }
         .emit();
 
         PoolEvent::DeletePool { pool_id: &U64(1) }.emit();
 
+        PoolEvent::Convert {
+            pool_id: &U64(1),
+            account_id: &bob(),
+            input_token_id: &usdc(),
+            filled_amount: &U128(8),
+            refunded_amount: &U128(2),
+            output_token_id: &usdt(),
+            output_amount: &U128(8),
+        }
+        .emit();
+
         assert_eq!(
             test_utils::get_logs()[0],
-            r#"EVENT_JSON:{"data":{"pool":{"creator":"bob.near","deposit_near_amount":"1","id":"1","in_token":"usdc","in_token_balance":"1","in_token_rate":0,"out_token":"usdt","out_token_balance":"1","out_token_rate":0,"reversible":false}},"pool_event":"create_pool","standard":"convertor","version":"1.0.0"}"#
+            r#"EVENT_JSON:{"data":{"pool":{"creator":"bob.near","deposit_near_amount":"1","end_time":null,"id":"1","in_token":"usdc","in_token_balance":"1","in_token_rate":0,"in_token_retirement":null,"is_paused":false,"is_private":false,"max_conversion_amount":null,"out_token":"usdt","out_token_balance":"1","out_token_rate":0,"pending_transfer_count":0,"reversible":false,"start_time":null,"statistics":{"conversion_count":"0","converted_in_token_amount":"0","converted_out_token_amount":"0"},"storage_sponsor_budget":"0","storage_usage":"0","user_quota":null,"volume_limit":null}},"pool_event":"create_pool","standard":"convertor","version":"1.0.0"}"#
         );
         assert_eq!(
             test_utils::get_logs()[1],
-            r#"EVENT_JSON:{"data":{"pool":{"creator":"bob.near","deposit_near_amount":"1","id":"1","in_token":"usdc","in_token_balance":"1","in_token_rate":0,"out_token":"usdt","out_token_balance":"1","out_token_rate":0,"reversible":false}},"pool_event":"update_pool","standard":"convertor","version":"1.0.0"}"#
+            r#"EVENT_JSON:{"data":{"pool":{"creator":"bob.near","deposit_near_amount":"1","end_time":null,"id":"1","in_token":"usdc","in_token_balance":"1","in_token_rate":0,"in_token_retirement":null,"is_paused":false,"is_private":false,"max_conversion_amount":null,"out_token":"usdt","out_token_balance":"1","out_token_rate":0,"pending_transfer_count":0,"reversible":false,"start_time":null,"statistics":{"conversion_count":"0","converted_in_token_amount":"0","converted_out_token_amount":"0"},"storage_sponsor_budget":"0","storage_usage":"0","user_quota":null,"volume_limit":null}},"pool_event":"update_pool","standard":"convertor","version":"1.0.0"}"#
         );
         assert_eq!(
             test_utils::get_logs()[2],
             r#"EVENT_JSON:{"data":{"pool_id":"1"},"pool_event":"delete_pool","standard":"convertor","version":"1.0.0"}"#
         );
+        assert_eq!(
+            test_utils::get_logs()[3],
+            r#"EVENT_JSON:{"data":{"account_id":"bob.near","filled_amount":"8","input_token_id":"usdc","output_amount":"8","output_token_id":"usdt","pool_id":"1","refunded_amount":"2"},"pool_event":"convert","standard":"convertor","version":"1.0.0"}"#
+        );
     }
 }
@@ -0,0 +1,119 @@
+//! Schemas of the released version, only used for upgrading state.
+use crate::account::{Account, VAccount};
+use crate::constants::{INIT_ACCOUNT_V1_STORAGE, U128_STORAGE, U32_STORAGE};
+use crate::conversion_pool::{ConversionPool, PoolStatistics, VPool};
+use crate::*;
+use near_sdk::json_types::U64;
+use std::collections::HashMap;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConversionPoolV1 {
+    pub id: PoolId,
+    pub creator: AccountId,
+    pub in_token: AccountId,
+    pub in_token_balance: U128,
+    pub out_token: AccountId,
+    pub out_token_balance: U128,
+    pub reversible: bool,
+    pub in_token_rate: u32,
+    pub out_token_rate: u32,
+    pub deposit_near_amount: U128,
+}
+
+impl From<ConversionPoolV1> for ConversionPool {
+    fn from(pool: ConversionPoolV1) -> Self {
+        Self {
+            id: pool.id,
+            creator: pool.creator,
+            in_token: pool.in_token,
+            in_token_balance: pool.in_token_balance,
+            out_token: pool.out_token,
+            out_token_balance: pool.out_token_balance,
+            reversible: pool.reversible,
+            in_token_rate: pool.in_token_rate,
+            out_token_rate: pool.out_token_rate,
+            deposit_near_amount: pool.deposit_near_amount,
+            is_paused: false,
+            statistics: PoolStatistics::new(),
+            max_conversion_amount: None,
+            volume_limit: None,
+            user_quota: None,
+            is_private: false,
+            start_time: None,
+            end_time: None,
+            in_token_retirement: None,
+            pending_transfer_count: 0,
+            storage_sponsor_budget: U128(0),
+            // the usage of pools in the released version isn't known, only later changes are measured.
+            storage_usage: U64(0),
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct AccountV1 {
+    pub near_amount_for_storage: Balance,
+    pub tokens: HashMap<AccountId, Balance>,
+    pub ft_transfer_lock: u32,
+}
+
+impl From<AccountV1> for Account {
+    fn from(account: AccountV1) -> Self {
+        // the record is charged as much as the released version did, the fields added since
+        // are paid by the contract and only later changes are measured.
+        // token entries are charged by their actual size, as removing them is measured.
+        let storage_usage = INIT_ACCOUNT_V1_STORAGE
+            + account
+                .tokens
+                .keys()
+                .map(|token_id| {
+                    U32_STORAGE + token_id.as_str().len() as StorageUsage + U128_STORAGE
+                })
+                .sum::<StorageUsage>();
+        Self {
+            near_amount_for_storage: account.near_amount_for_storage,
+            tokens: account.tokens,
+            ft_transfer_lock: account.ft_transfer_lock,
+            in_flight_transfers: vec![],
+            next_transfer_nonce: 0,
+            sponsored_storage_amount: 0,
+            storage_sponsor_pool_id: None,
+            conversion_count: 0,
+            quota_pool_ids: vec![],
+            storage_usage,
+            is_unregistering: false,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct FtMetaDataV1 {
+    pub token_id: AccountId,
+    pub decimals: u8,
+}
+
+impl From<FtMetaDataV1> for FtMetaData {
+    fn from(token: FtMetaDataV1) -> Self {
+        Self {
+            token_id: token.token_id,
+            decimals: token.decimals,
+            symbol: None,
+            name: None,
+            icon: None,
+            reference: None,
+            tags: vec![],
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenConvertorV1 {
+    pub owner: AccountId,
+    pub accounts: LookupMap<AccountId, VAccount>,
+    pub pools: UnorderedMap<PoolId, VPool>,
+    pub whitelisted_tokens: UnorderedMap<AccountId, FtMetaDataV1>,
+    pub create_pool_deposit: Balance,
+    pub pool_id: u64,
+    pub contract_is_paused: bool,
+}
@@ -1,28 +1,39 @@
 extern crate core;
 
 pub mod account;
+pub mod allowlist;
 pub mod constants;
 pub mod contract_interfaces;
 pub mod contract_viewers;
 pub mod conversion_pool;
 pub mod events;
+pub mod legacy;
 pub mod owner;
+pub mod pool_index;
+pub mod quota;
+pub mod solvency;
+pub mod sponsor;
 pub mod storage_impl;
 pub mod token_receiver;
 pub mod types;
+pub mod upgrade;
 
 use crate::account::VAccount;
 use crate::conversion_pool::VPool;
+use crate::pool_index::PoolIndex;
+use crate::quota::QuotaUsage;
 pub use crate::types::FtMetaData;
+use crate::upgrade::write_state_version;
 use itertools::Itertools;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, log, near_bindgen, serde_json, AccountId, Balance, BorshStorageKey, Gas, PanicOnDefault,
-    Promise, PromiseOrValue, PromiseResult,
+    env, log, near_bindgen, serde_json, AccountId, Balance, BorshStorageKey, CryptoHash, Gas,
+    PanicOnDefault, Promise, PromiseOrValue, PromiseResult, StorageUsage, Timestamp,
 };
+use std::collections::HashMap;
 use types::PoolId;
 
 #[near_bindgen]
@@ -31,12 +42,40 @@ pub struct TokenConvertor {
     pub owner: AccountId,
     pub accounts: LookupMap<AccountId, VAccount>,
     pub pools: UnorderedMap<PoolId, VPool>,
+    pub pool_index: PoolIndex,
+    // usage of quota by accounts in pools which have user quota.
+    pub quota_usages: LookupMap<(PoolId, AccountId), QuotaUsage>,
+    // accounts which have quota usages in each pool.
+    pub pool_quota_accounts: LookupMap<PoolId, UnorderedSet<AccountId>>,
+    // accounts allowed to convert in private pools.
+    pub pool_allowlists: LookupMap<PoolId, UnorderedSet<AccountId>>,
+    // total balances of each token in pools and accounts.
+    pub token_liabilities: LookupMap<AccountId, Balance>,
+    // total amounts of each token being transferred out of the contract.
+    pub token_in_flight_amounts: LookupMap<AccountId, Balance>,
+    // tokens paused by reconciliation or owner, conversions with them are disabled.
+    pub paused_tokens: UnorderedSet<AccountId>,
+    // tokens failed to be transferred to accounts which can't receive them in the contract.
+    pub lost_found: LookupMap<AccountId, HashMap<AccountId, Balance>>,
+    // near deposited by owner to pay storage of new accounts for all pools.
+    pub storage_sponsor_budget: Balance,
     pub whitelisted_tokens: UnorderedMap<AccountId, FtMetaData>,
+    // tokens removed from whitelist while some pools still use them.
+    pub delisted_tokens: UnorderedMap<AccountId, FtMetaData>,
     // request deposit some near when creating pool.owner can change it.
     pub create_pool_deposit: Balance,
     // auto increase id.
     pub pool_id: u64,
     pub contract_is_paused: bool,
+    // wasm code staged by owner, it can be deployed after a delay.
+    pub staged_code: LazyOption<Vec<u8>>,
+    pub staged_code_hash: Option<CryptoHash>,
+    pub staged_code_timestamp: Timestamp,
+    // pools of the released version which are not migrated yet,
+    // indexes and liabilities of a pool are built when it's migrated.
+    pub legacy_pool_count: u64,
+    // all accounts of the released version are migrated, their token balances are counted in liabilities.
+    pub legacy_accounts_migrated: bool,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -44,20 +83,65 @@ pub(crate) enum StorageKey {
     Pools,
     Accounts,
     WhitelistedTokens,
+    StagedCode,
+    DelistedTokens,
+    PoolIdsByToken,
+    PoolIdsByTokenInner {
+        token_id: AccountId,
+    },
+    PoolIdsByPair,
+    PoolIdsByPairInner {
+        in_token: AccountId,
+        out_token: AccountId,
+    },
+    PoolIdsByCreator,
+    PoolIdsByCreatorInner {
+        account_id: AccountId,
+    },
+    QuotaUsages,
+    PoolAllowlists,
+    PoolAllowlist {
+        pool_id: PoolId,
+    },
+    TokenLiabilities,
+    TokenInFlightAmounts,
+    PausedTokens,
+    LostFound,
+    PoolQuotaAccounts,
+    PoolQuotaAccountsInner {
+        pool_id: PoolId,
+    },
+    StateVersion,
 }
 
 #[near_bindgen]
 impl TokenConvertor {
     #[init]
     pub fn new(owner: AccountId, create_pool_deposit: U128) -> Self {
+        write_state_version();
         Self {
             owner,
             accounts: LookupMap::new(StorageKey::Accounts),
             pools: UnorderedMap::new(StorageKey::Pools),
+            pool_index: PoolIndex::new(),
+            quota_usages: LookupMap::new(StorageKey::QuotaUsages),
+            pool_quota_accounts: LookupMap::new(StorageKey::PoolQuotaAccounts),
+            pool_allowlists: LookupMap::new(StorageKey::PoolAllowlists),
+            token_liabilities: LookupMap::new(StorageKey::TokenLiabilities),
+            token_in_flight_amounts: LookupMap::new(StorageKey::TokenInFlightAmounts),
+            paused_tokens: UnorderedSet::new(StorageKey::PausedTokens),
+            lost_found: LookupMap::new(StorageKey::LostFound),
+            storage_sponsor_budget: 0,
             whitelisted_tokens: UnorderedMap::new(StorageKey::WhitelistedTokens),
+            delisted_tokens: UnorderedMap::new(StorageKey::DelistedTokens),
             create_pool_deposit: create_pool_deposit.0,
             pool_id: 0,
             contract_is_paused: false,
+            staged_code: LazyOption::new(StorageKey::StagedCode, None),
+            staged_code_hash: None,
+            staged_code_timestamp: 0,
+            legacy_pool_count: 0,
+            legacy_accounts_migrated: true,
         }
     }
 
@@ -69,12 +153,27 @@ impl TokenConvertor {
         );
     }
 
-    pub(crate) fn assert_create_pool_deposit_amount(&self) {
-        assert_eq!(
-            env::attached_deposit(),
-            self.create_pool_deposit,
-            "Creating a pool requires a deposit of '{}' yocto NEAR.",
-            self.create_pool_deposit
+    pub(crate) fn assert_token_is_not_delisted(&self, token: &AccountId) {
+        assert!(
+            self.delisted_tokens.get(token).is_none(),
+            "Token '{}' is delisted.",
+            token
+        );
+    }
+
+    pub(crate) fn assert_token_is_not_paused(&self, token: &AccountId) {
+        assert!(
+            !self.paused_tokens.contains(token),
+            "Token '{}' is paused.",
+            token
+        );
+    }
+
+    /// liabilities are only complete after pools and accounts of the released version are migrated.
+    pub(crate) fn assert_legacy_state_migrated(&self) {
+        assert!(
+            self.legacy_pool_count == 0 && self.legacy_accounts_migrated,
+            "Pools or accounts of the released version are not migrated yet."
         );
     }
 
@@ -85,9 +184,9 @@ impl TokenConvertor {
     pub(crate) fn assert_storage_balance_bound_min(&self, account_id: &AccountId) {
         let account = self
             .internal_get_account(account_id)
-            .expect(format!("The account '{}' is not registered.", account_id).as_str());
+            .unwrap_or_else(|| panic!("The account '{}' is not registered.", account_id));
 
-        let min_bound = self.internal_get_storage_balance_min_bound(&account_id);
+        let min_bound = self.internal_get_storage_balance_min_bound(account_id);
         assert!(
             account.near_amount_for_storage >= min_bound,
             "Need to deposit at least '{}' yocto NEAR as storage fee.",
@@ -99,6 +198,7 @@ impl TokenConvertor {
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 pub mod test {
+    use crate::types::FtMetaData;
     use crate::TokenConvertor;
     use near_sdk::json_types::U128;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
@@ -113,6 +213,21 @@ pub mod test {
         AccountId::new_unchecked("usdc".to_string())
     }
 
+    /// whitelist usdc and usdt with the same decimals
+    pub fn whitelist_tokens(contract: &mut TokenConvertor) {
+        for token_id in [usdc(), usdt()] {
+            contract.internal_whitelist_token(&FtMetaData {
+                token_id,
+                decimals: 6,
+                symbol: None,
+                name: None,
+                icon: None,
+                reference: None,
+                tags: vec![],
+            });
+        }
+    }
+
     pub fn setup_contract() -> (VMContextBuilder, TokenConvertor, AccountId) {
         let mut context = VMContextBuilder::new();
         testing_env!(context.predecessor_account_id(accounts(0)).build());
@@ -1,7 +1,21 @@
+use crate::constants::{
+    T_GAS_FOR_FT_BALANCE_OF, T_GAS_FOR_FT_METADATA, T_GAS_FOR_RESOLVE_FT_METADATA,
+    T_GAS_FOR_RESOLVE_RECOVER_SURPLUS,
+};
 use crate::contract_interfaces::OwnerAction;
 use crate::types::FtMetaData;
 use crate::*;
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_contract_standards::upgrade::Ownable;
+use near_sdk::ext_contract;
+use std::ops::Mul;
+
+/// metadata of a token contract, see https://nomicon.io/Standards/Tokens/FungibleToken/Metadata
+#[ext_contract(ext_ft_metadata)]
+pub trait FtMetadataProvider {
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
+}
 
 #[near_bindgen]
 impl Ownable for TokenConvertor {
@@ -20,14 +34,48 @@ impl OwnerAction for TokenConvertor {
     fn extend_whitelisted_tokens(&mut self, tokens: Vec<FtMetaData>) {
         self.assert_owner();
         for token in tokens {
-            self.whitelisted_tokens.insert(&token.token_id, &token);
+            self.internal_whitelist_token(&token);
         }
     }
 
+    fn whitelist_token(&mut self, token_id: AccountId) -> Promise {
+        self.assert_owner();
+        ext_ft_metadata::ext(token_id.clone())
+            .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_FT_METADATA))
+            .ft_metadata()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_FT_METADATA))
+                    .ft_metadata_resolved(token_id),
+            )
+    }
+
+    fn set_whitelisted_token_tags(&mut self, token_id: AccountId, tags: Vec<String>) {
+        self.assert_owner();
+        let mut token = self
+            .whitelisted_tokens
+            .get(&token_id)
+            .unwrap_or_else(|| panic!("Token '{}' is not in the whitelist.", token_id));
+        token.tags = tags;
+        self.whitelisted_tokens.insert(&token_id, &token);
+    }
+
+    /// if some pools still use the token, it will be delisted instead of removed,
+    /// conversions in these pools are disabled but tokens can still be withdrawn.
     fn remove_whitelisted_tokens(&mut self, tokens: Vec<AccountId>) {
         self.assert_owner();
+        // pools of the released version are not in the index until they are migrated.
+        assert_eq!(
+            self.legacy_pool_count, 0,
+            "Pools of the released version are not migrated yet."
+        );
         for e in tokens {
-            self.whitelisted_tokens.remove(&e);
+            if let Some(token) = self.whitelisted_tokens.remove(&e) {
+                if self.pool_index.is_token_used(&e) {
+                    self.delisted_tokens.insert(&e, &token);
+                    log!("Token '{}' is delisted since it's still used by pools.", e);
+                }
+            }
         }
     }
 
@@ -37,6 +85,42 @@ impl OwnerAction for TokenConvertor {
         self.create_pool_deposit = amount.0;
     }
 
+    fn recover_surplus(&mut self, token_id: AccountId, receiver_id: AccountId) -> Promise {
+        self.assert_owner();
+        // balances of accounts which are not migrated are not counted in liabilities yet.
+        self.assert_legacy_state_migrated();
+        ext_ft_core::ext(token_id.clone())
+            .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_FT_BALANCE_OF))
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_RECOVER_SURPLUS))
+                    .recover_surplus_resolved(
+                        token_id.clone(),
+                        receiver_id,
+                        U128(self.internal_get_liability(&token_id)),
+                    ),
+            )
+    }
+
+    fn pause_token(&mut self, token_id: AccountId) {
+        self.assert_owner();
+        assert!(
+            self.paused_tokens.insert(&token_id),
+            "Token '{}' is already paused.",
+            token_id
+        );
+    }
+
+    fn resume_token(&mut self, token_id: AccountId) {
+        self.assert_owner();
+        assert!(
+            self.paused_tokens.remove(&token_id),
+            "Token '{}' is not paused.",
+            token_id
+        );
+    }
+
     fn pause_contract(&mut self) {
         self.assert_owner();
         assert!(!self.contract_is_paused, "Contract is already paused.");
@@ -49,3 +133,106 @@ impl OwnerAction for TokenConvertor {
         self.contract_is_paused = false;
     }
 }
+
+#[near_bindgen]
+impl TokenConvertor {
+    #[private]
+    pub fn ft_metadata_resolved(&mut self, token_id: AccountId) -> FtMetaData {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expect 1 promise result for getting token metadata."
+        );
+        let metadata: FungibleTokenMetadata = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice(&value)
+                .unwrap_or_else(|_| panic!("Invalid metadata of token '{}'.", token_id)),
+            PromiseResult::Failed => panic!("Failed to get metadata of token '{}'.", token_id),
+        };
+        let mut token = FtMetaData::from_metadata(token_id, metadata);
+        // keep the tags if the token is whitelisted again.
+        if let Some(old_token) = self.whitelisted_tokens.get(&token.token_id) {
+            token.tags = old_token.tags;
+        }
+        self.internal_whitelist_token(&token);
+        token
+    }
+}
+
+impl TokenConvertor {
+    pub(crate) fn internal_whitelist_token(&mut self, token: &FtMetaData) {
+        self.delisted_tokens.remove(&token.token_id);
+        self.whitelisted_tokens.insert(&token.token_id, token);
+    }
+
+    /// remove the delisted token once no pool uses it.
+    pub(crate) fn internal_clean_delisted_token(&mut self, token_id: &AccountId) {
+        if self.delisted_tokens.get(token_id).is_some() && !self.pool_index.is_token_used(token_id)
+        {
+            self.delisted_tokens.remove(token_id);
+            log!("Delisted token '{}' is removed.", token_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{setup_contract, usdt};
+    use near_contract_standards::fungible_token::metadata::FT_METADATA_SPEC;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn usdt_metadata() -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Tether USD".to_string(),
+            symbol: "USDT".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 6,
+        }
+    }
+
+    #[test]
+    fn test_ft_metadata_resolved() {
+        let (context, mut contract, _) = setup_contract();
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&usdt_metadata()).unwrap()
+            )],
+        );
+        contract.ft_metadata_resolved(usdt());
+        let token = contract.whitelisted_tokens.get(&usdt()).unwrap();
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.symbol, Some("USDT".to_string()));
+        assert_eq!(token.name, Some("Tether USD".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pools of the released version are not migrated yet.")]
+    fn test_remove_whitelisted_tokens_before_migrating_pools() {
+        let (mut context, mut contract, owner) = setup_contract();
+        contract.legacy_pool_count = 1;
+        testing_env!(context.predecessor_account_id(owner).build());
+        contract.remove_whitelisted_tokens(vec![usdt()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to get metadata of token 'usdt'.")]
+    fn test_ft_metadata_resolved_failed() {
+        let (context, mut contract, _) = setup_contract();
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.ft_metadata_resolved(usdt());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::conversion_pool::ConversionPool;
+use crate::*;
+use near_sdk::collections::UnorderedSet;
+
+/// Secondary indexes of pools, they are maintained when a pool is created or deleted,
+/// so that querying pools by token, pair or creator doesn't need to scan all pools.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PoolIndex {
+    pub pool_ids_by_token: LookupMap<AccountId, UnorderedSet<PoolId>>,
+    /// key is (in_token, out_token) of pool
+    pub pool_ids_by_pair: LookupMap<(AccountId, AccountId), UnorderedSet<PoolId>>,
+    pub pool_ids_by_creator: LookupMap<AccountId, UnorderedSet<PoolId>>,
+}
+
+impl PoolIndex {
+    pub fn new() -> Self {
+        Self {
+            pool_ids_by_token: LookupMap::new(StorageKey::PoolIdsByToken),
+            pool_ids_by_pair: LookupMap::new(StorageKey::PoolIdsByPair),
+            pool_ids_by_creator: LookupMap::new(StorageKey::PoolIdsByCreator),
+        }
+    }
+}
+
+impl Default for PoolIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PoolIndex {
+    pub fn add_pool(&mut self, pool: &ConversionPool) {
+        for token_id in [&pool.in_token, &pool.out_token] {
+            let mut pool_ids = self.pool_ids_by_token.get(token_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::PoolIdsByTokenInner {
+                    token_id: token_id.clone(),
+                })
+            });
+            pool_ids.insert(&pool.id);
+            self.pool_ids_by_token.insert(token_id, &pool_ids);
+        }
+
+        let pair = (pool.in_token.clone(), pool.out_token.clone());
+        let mut pool_ids = self.pool_ids_by_pair.get(&pair).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::PoolIdsByPairInner {
+                in_token: pair.0.clone(),
+                out_token: pair.1.clone(),
+            })
+        });
+        pool_ids.insert(&pool.id);
+        self.pool_ids_by_pair.insert(&pair, &pool_ids);
+
+        let mut pool_ids = self
+            .pool_ids_by_creator
+            .get(&pool.creator)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::PoolIdsByCreatorInner {
+                    account_id: pool.creator.clone(),
+                })
+            });
+        pool_ids.insert(&pool.id);
+        self.pool_ids_by_creator.insert(&pool.creator, &pool_ids);
+    }
+
+    pub fn remove_pool(&mut self, pool: &ConversionPool) {
+        for token_id in [&pool.in_token, &pool.out_token] {
+            if let Some(mut pool_ids) = self.pool_ids_by_token.get(token_id) {
+                pool_ids.remove(&pool.id);
+                if pool_ids.is_empty() {
+                    self.pool_ids_by_token.remove(token_id);
+                } else {
+                    self.pool_ids_by_token.insert(token_id, &pool_ids);
+                }
+            }
+        }
+
+        let pair = (pool.in_token.clone(), pool.out_token.clone());
+        if let Some(mut pool_ids) = self.pool_ids_by_pair.get(&pair) {
+            pool_ids.remove(&pool.id);
+            if pool_ids.is_empty() {
+                self.pool_ids_by_pair.remove(&pair);
+            } else {
+                self.pool_ids_by_pair.insert(&pair, &pool_ids);
+            }
+        }
+
+        if let Some(mut pool_ids) = self.pool_ids_by_creator.get(&pool.creator) {
+            pool_ids.remove(&pool.id);
+            if pool_ids.is_empty() {
+                self.pool_ids_by_creator.remove(&pool.creator);
+            } else {
+                self.pool_ids_by_creator.insert(&pool.creator, &pool_ids);
+            }
+        }
+    }
+
+    pub fn is_token_used(&self, token_id: &AccountId) -> bool {
+        self.pool_ids_by_token.contains_key(token_id)
+    }
+
+    pub fn get_pool_ids_by_token(
+        &self,
+        token_id: &AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<PoolId> {
+        self.pool_ids_by_token
+            .get(token_id)
+            .map(|pool_ids| {
+                pool_ids
+                    .iter()
+                    .skip(from_index as usize)
+                    .take(limit as usize)
+                    .collect_vec()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_pool_ids_by_pair(&self, in_token: &AccountId, out_token: &AccountId) -> Vec<PoolId> {
+        self.pool_ids_by_pair
+            .get(&(in_token.clone(), out_token.clone()))
+            .map(|pool_ids| pool_ids.to_vec())
+            .unwrap_or_default()
+    }
+
+    pub fn get_pool_ids_by_creator(
+        &self,
+        account_id: &AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<PoolId> {
+        self.pool_ids_by_creator
+            .get(account_id)
+            .map(|pool_ids| {
+                pool_ids
+                    .iter()
+                    .skip(from_index as usize)
+                    .take(limit as usize)
+                    .collect_vec()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{setup_contract, usdc, usdt};
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::test_env::{alice, bob};
+
+    fn pool(
+        id: u64,
+        creator: AccountId,
+        in_token: AccountId,
+        out_token: AccountId,
+    ) -> ConversionPool {
+        ConversionPool::new(U64(id), creator, in_token, out_token, false, 1, 1, U128(0))
+    }
+
+    #[test]
+    fn test_pool_index() {
+        setup_contract();
+        let mut index = PoolIndex::new();
+        let pool1 = pool(1, alice(), usdc(), usdt());
+        let pool2 = pool(2, bob(), usdt(), usdc());
+        let pool3 = pool(3, alice(), usdc(), usdt());
+        index.add_pool(&pool1);
+        index.add_pool(&pool2);
+        index.add_pool(&pool3);
+
+        assert_eq!(index.get_pool_ids_by_token(&usdc(), 0, 10).len(), 3);
+        assert_eq!(index.get_pool_ids_by_token(&usdc(), 1, 10).len(), 2);
+        assert_eq!(
+            index.get_pool_ids_by_pair(&usdc(), &usdt()),
+            vec![U64(1), U64(3)]
+        );
+        assert_eq!(index.get_pool_ids_by_pair(&usdt(), &usdc()), vec![U64(2)]);
+        assert_eq!(index.get_pool_ids_by_creator(&alice(), 0, 1), vec![U64(1)]);
+
+        index.remove_pool(&pool1);
+        index.remove_pool(&pool3);
+        assert_eq!(index.get_pool_ids_by_pair(&usdc(), &usdt()), vec![]);
+        assert!(index.get_pool_ids_by_creator(&alice(), 0, 10).is_empty());
+        assert!(index.is_token_used(&usdc()));
+
+        index.remove_pool(&pool2);
+        assert!(!index.is_token_used(&usdc()));
+        assert!(!index.is_token_used(&usdt()));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+/// limit the amount of input token each account can convert in a pool within a window,
+/// the window of an account restarts at its first conversion after the window ends.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UserQuota {
+    /// window length in nanoseconds
+    pub window_duration: U64,
+    pub max_amount: U128,
+}
+
+impl UserQuota {
+    pub fn new(window_duration: U64, max_amount: U128) -> Self {
+        assert!(
+            window_duration.0 > 0,
+            "Window duration should be greater than 0."
+        );
+        Self {
+            window_duration,
+            max_amount,
+        }
+    }
+}
+
+/// usage of quota by an account in a pool
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct QuotaUsage {
+    pub window_start: Timestamp,
+    pub used_amount: Balance,
+}
+
+impl QuotaUsage {
+    fn get_used_amount(&self, quota: &UserQuota) -> Balance {
+        if env::block_timestamp() >= self.window_start + quota.window_duration.0 {
+            0
+        } else {
+            self.used_amount
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UserQuotaView {
+    pub window_duration: U64,
+    pub max_amount: U128,
+    pub used_amount: U128,
+    pub remaining_amount: U128,
+}
+
+impl TokenConvertor {
+    /// remaining quota of the account in the pool, none means the pool has no quota.
+    pub(crate) fn internal_get_remaining_quota(
+        &self,
+        pool_id: &PoolId,
+        account_id: &AccountId,
+    ) -> Option<Balance> {
+        self.internal_get_user_quota(pool_id, account_id)
+            .map(|view| view.remaining_amount.0)
+    }
+
+    pub(crate) fn internal_get_user_quota(
+        &self,
+        pool_id: &PoolId,
+        account_id: &AccountId,
+    ) -> Option<UserQuotaView> {
+        let quota = self
+            .internal_get_pool(pool_id)
+            .expect("No such pool.")
+            .user_quota?;
+        let used_amount = self
+            .quota_usages
+            .get(&(*pool_id, account_id.clone()))
+            .map(|usage| usage.get_used_amount(&quota))
+            .unwrap_or(0);
+        Some(UserQuotaView {
+            window_duration: quota.window_duration,
+            max_amount: quota.max_amount,
+            used_amount: U128(used_amount),
+            remaining_amount: U128(quota.max_amount.0.saturating_sub(used_amount)),
+        })
+    }
+
+    /// record the amount converted by the account if the pool has quota.
+    /// storage of a new usage is charged from the account's storage deposit.
+    pub(crate) fn internal_use_quota(
+        &mut self,
+        pool_id: &PoolId,
+        account_id: &AccountId,
+        quota: &UserQuota,
+        amount: Balance,
+    ) {
+        let key = (*pool_id, account_id.clone());
+        let usage = self.quota_usages.get(&key);
+        let mut new_usage = match &usage {
+            Some(usage) if usage.get_used_amount(quota) > 0 => usage.clone(),
+            _ => QuotaUsage {
+                window_start: env::block_timestamp(),
+                used_amount: 0,
+            },
+        };
+        assert!(
+            new_usage.used_amount + amount <= quota.max_amount.0,
+            "Failed to convert. Quota of '{}' in pool '{}' is exceeded, the remaining quota is {}.",
+            account_id,
+            pool_id.0,
+            quota.max_amount.0 - new_usage.used_amount
+        );
+        new_usage.used_amount += amount;
+        let initial_storage_usage = env::storage_usage();
+        self.quota_usages.insert(&key, &new_usage);
+        if usage.is_none() {
+            let mut accounts = self.pool_quota_accounts.get(pool_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::PoolQuotaAccountsInner { pool_id: *pool_id })
+            });
+            accounts.insert(account_id);
+            self.pool_quota_accounts.insert(pool_id, &accounts);
+            let quota_storage_usage = env::storage_usage() - initial_storage_usage;
+            self.internal_use_account(account_id, |account| {
+                account.quota_pool_ids.push(*pool_id);
+                account.storage_usage += quota_storage_usage;
+            });
+            log!(
+                "{} bytes of storage is used by quota of '{}' in pool '{}'.",
+                quota_storage_usage,
+                account_id,
+                pool_id.0
+            );
+        }
+    }
+
+    /// remove the quota usage of the account in the pool, return the bytes released.
+    pub(crate) fn internal_remove_quota_usage(
+        &mut self,
+        pool_id: &PoolId,
+        account_id: &AccountId,
+    ) -> StorageUsage {
+        let initial_storage_usage = env::storage_usage();
+        self.quota_usages.remove(&(*pool_id, account_id.clone()));
+        if let Some(mut accounts) = self.pool_quota_accounts.get(pool_id) {
+            accounts.remove(account_id);
+            if accounts.is_empty() {
+                self.pool_quota_accounts.remove(pool_id);
+            } else {
+                self.pool_quota_accounts.insert(pool_id, &accounts);
+            }
+        }
+        initial_storage_usage.saturating_sub(env::storage_usage())
+    }
+}
+
+#[near_bindgen]
+impl TokenConvertor {
+    /// quota usages are kept when a pool is deleted, as there may be too many of them to remove at once.
+    /// anyone can remove them in batches, the released storage is no longer counted in the accounts.
+    /// return the number of removed quota usages.
+    pub fn clear_pool_quota_usages(&mut self, pool_id: PoolId, limit: u32) -> u32 {
+        assert!(
+            self.internal_get_pool(&pool_id).is_none(),
+            "Quota usages of pool '{}' can only be cleared after it's deleted.",
+            pool_id.0
+        );
+        let account_ids = match self.pool_quota_accounts.get(&pool_id) {
+            Some(accounts) => accounts.iter().take(limit as usize).collect_vec(),
+            None => return 0,
+        };
+        for account_id in &account_ids {
+            let released_storage = self.internal_remove_quota_usage(&pool_id, account_id);
+            self.internal_use_account(account_id, |account| {
+                account.quota_pool_ids.retain(|id| *id != pool_id);
+                account.storage_usage = account.storage_usage.saturating_sub(released_storage);
+            });
+        }
+        account_ids.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::conversion_pool::ConversionPool;
+    use crate::test::{setup_contract, usdc, usdt};
+    use near_sdk::test_utils::test_env::bob;
+    use near_sdk::testing_env;
+
+    fn setup_quota_pool(contract: &mut TokenConvertor) {
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        pool.user_quota = Some(UserQuota::new(U64(1000), U128(30)));
+        contract.pools.insert(&U64(1), &pool.into());
+    }
+
+    #[test]
+    fn test_user_quota() {
+        let (mut context, mut contract, _) = setup_contract();
+        setup_quota_pool(&mut contract);
+        assert_eq!(
+            contract.internal_get_remaining_quota(&U64(1), &bob()),
+            Some(30)
+        );
+
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        let quota = contract.internal_get_user_quota(&U64(1), &bob()).unwrap();
+        assert_eq!(quota.used_amount, U128(20));
+        assert_eq!(quota.remaining_amount, U128(10));
+        let account = contract.internal_get_account(&bob()).unwrap();
+        assert_eq!(account.quota_pool_ids, vec![U64(1)]);
+
+        testing_env!(context
+            .block_timestamp(env::block_timestamp() + 1000)
+            .build());
+        assert_eq!(
+            contract.internal_get_remaining_quota(&U64(1), &bob()),
+            Some(30)
+        );
+        contract.internal_convert(U64(1), &bob(), &usdc(), 30);
+        let account = contract.internal_get_account(&bob()).unwrap();
+        assert_eq!(account.quota_pool_ids, vec![U64(1)]);
+    }
+
+    #[test]
+    fn test_clear_pool_quota_usages() {
+        let (mut context, mut contract, _) = setup_contract();
+        setup_quota_pool(&mut contract);
+        let account = contract.internal_get_account(&bob()).unwrap();
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        assert!(contract.pool_quota_accounts.get(&U64(1)).is_some());
+        let quota_storage_usage =
+            contract.internal_get_account(&bob()).unwrap().storage_usage - account.storage_usage;
+
+        // storage of quota usages is released from accounts after the pool is deleted.
+        contract.pools.remove(&U64(1));
+        let initial_storage_usage = env::storage_usage();
+        assert_eq!(contract.clear_pool_quota_usages(U64(1), 10), 1);
+        assert_eq!(contract.clear_pool_quota_usages(U64(1), 10), 0);
+        assert!(contract.quota_usages.get(&(U64(1), bob())).is_none());
+        assert!(contract.pool_quota_accounts.get(&U64(1)).is_none());
+        let new_account = contract.internal_get_account(&bob()).unwrap();
+        assert!(new_account.quota_pool_ids.is_empty());
+        assert_eq!(new_account.storage_usage, account.storage_usage);
+        assert_eq!(
+            env::storage_usage(),
+            initial_storage_usage - quota_storage_usage
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Quota usages of pool '1' can only be cleared after it's deleted.")]
+    fn test_clear_quota_usages_of_existing_pool() {
+        let (_, mut contract, _) = setup_contract();
+        setup_quota_pool(&mut contract);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        contract.clear_pool_quota_usages(U64(1), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Quota of 'bob.near' in pool '1' is exceeded")]
+    fn test_user_quota_exceeded() {
+        let (_, mut contract, _) = setup_contract();
+        setup_quota_pool(&mut contract);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 20);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 11);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::constants::{
+    T_GAS_FOR_FT_BALANCE_OF, T_GAS_FOR_FT_TRANSFER, T_GAS_FOR_RESOLVE_RECONCILE,
+    T_GAS_FOR_RESOLVE_TRANSFER,
+};
+use crate::contract_interfaces::SolvencyAction;
+use crate::events::{EventEmit, ReconcileEvent, TokenEvent};
+use crate::*;
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::ONE_YOCTO;
+use std::ops::Mul;
+
+fn increase_ledger(
+    ledger: &mut LookupMap<AccountId, Balance>,
+    token_id: &AccountId,
+    amount: Balance,
+) {
+    if amount == 0 {
+        return;
+    }
+    let balance = ledger.get(token_id).unwrap_or(0);
+    ledger.insert(
+        token_id,
+        &balance
+            .checked_add(amount)
+            .expect("Ledger of token overflowed."),
+    );
+}
+
+fn decrease_ledger(
+    ledger: &mut LookupMap<AccountId, Balance>,
+    token_id: &AccountId,
+    amount: Balance,
+) {
+    if amount == 0 {
+        return;
+    }
+    let balance = ledger.get(token_id).unwrap_or(0);
+    debug_assert!(
+        balance >= amount,
+        "Ledger of token '{}' can't be negative.",
+        token_id
+    );
+    let balance = balance.saturating_sub(amount);
+    if balance == 0 {
+        ledger.remove(token_id);
+    } else {
+        ledger.insert(token_id, &balance);
+    }
+}
+
+impl TokenConvertor {
+    /// liabilities of a token are the total balances of it in pools and accounts,
+    /// which the contract should hold.
+    pub(crate) fn internal_get_liability(&self, token_id: &AccountId) -> Balance {
+        self.token_liabilities.get(token_id).unwrap_or(0)
+    }
+
+    pub(crate) fn internal_increase_liability(&mut self, token_id: &AccountId, amount: Balance) {
+        increase_ledger(&mut self.token_liabilities, token_id, amount);
+    }
+
+    pub(crate) fn internal_decrease_liability(&mut self, token_id: &AccountId, amount: Balance) {
+        decrease_ledger(&mut self.token_liabilities, token_id, amount);
+    }
+
+    /// amount of a token which has left liabilities but whose transfer is not resolved yet.
+    pub(crate) fn internal_get_in_flight_amount(&self, token_id: &AccountId) -> Balance {
+        self.token_in_flight_amounts.get(token_id).unwrap_or(0)
+    }
+
+    pub(crate) fn internal_increase_in_flight_amount(
+        &mut self,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        increase_ledger(&mut self.token_in_flight_amounts, token_id, amount);
+    }
+
+    pub(crate) fn internal_decrease_in_flight_amount(
+        &mut self,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        decrease_ledger(&mut self.token_in_flight_amounts, token_id, amount);
+    }
+}
+
+#[near_bindgen]
+impl SolvencyAction for TokenConvertor {
+    fn reconcile(&mut self, token_id: AccountId) -> Promise {
+        self.assert_legacy_state_migrated();
+        ext_ft_core::ext(token_id.clone())
+            .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_FT_BALANCE_OF))
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_RECONCILE))
+                    .reconcile_resolved(
+                        token_id.clone(),
+                        U128(self.internal_get_liability(&token_id)),
+                    ),
+            )
+    }
+}
+
+#[near_bindgen]
+impl TokenConvertor {
+    /// surplus is the balance beyond the larger one of `liabilities` when recovering started
+    /// and the current liabilities, plus tokens in flight which may be refunded.
+    /// the surplus being transferred is counted as in flight, so it can't be recovered twice.
+    #[private]
+    pub fn recover_surplus_resolved(
+        &mut self,
+        token_id: AccountId,
+        receiver_id: AccountId,
+        liabilities: U128,
+    ) -> U128 {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expect 1 promise result for getting token balance."
+        );
+        let balance: U128 = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice(&value)
+                .unwrap_or_else(|_| panic!("Invalid balance of token '{}'.", token_id)),
+            PromiseResult::Failed => panic!("Failed to get balance of token '{}'.", token_id),
+        };
+        let reserved = std::cmp::max(liabilities.0, self.internal_get_liability(&token_id))
+            + self.internal_get_in_flight_amount(&token_id);
+        let surplus = balance.0.saturating_sub(reserved);
+        if surplus == 0 {
+            log!("No surplus of token '{}' to recover.", token_id);
+            return U128(0);
+        }
+        self.internal_increase_in_flight_amount(&token_id, surplus);
+        log!(
+            "Recover surplus {} of token '{}' to '{}'.",
+            surplus,
+            token_id,
+            receiver_id
+        );
+        ext_ft_core::ext(token_id.clone())
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_FT_TRANSFER))
+            .ft_transfer(receiver_id, U128(surplus), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_TRANSFER))
+                    .surplus_transfer_resolved(token_id, U128(surplus)),
+            );
+        U128(surplus)
+    }
+
+    /// the surplus stays in the contract if the transfer failed, so only in flight amount is reverted.
+    #[private]
+    pub fn surplus_transfer_resolved(&mut self, token_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expect 1 promise result for transferring surplus."
+        );
+        self.internal_decrease_in_flight_amount(&token_id, amount.0);
+        if let PromiseResult::Failed = env::promise_result(0) {
+            log!("Failed to recover surplus of token '{}'.", token_id);
+        }
+    }
+
+    /// `liabilities` is the liabilities when reconciliation started, the smaller one of it
+    /// and the current liabilities is compared, because tokens received or sent
+    /// between the two moments may not be reflected in the balance.
+    /// tokens in flight are not compared, since they may have left the contract already.
+    #[private]
+    pub fn reconcile_resolved(&mut self, token_id: AccountId, liabilities: U128) -> ReconcileEvent {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expect 1 promise result for getting token balance."
+        );
+        let balance: U128 = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice(&value)
+                .unwrap_or_else(|_| panic!("Invalid balance of token '{}'.", token_id)),
+            PromiseResult::Failed => panic!("Failed to get balance of token '{}'.", token_id),
+        };
+        let liabilities = std::cmp::min(liabilities.0, self.internal_get_liability(&token_id));
+        let event = ReconcileEvent {
+            token_id: token_id.clone(),
+            balance,
+            liabilities: U128(liabilities),
+            in_flight_amount: U128(self.internal_get_in_flight_amount(&token_id)),
+            is_under_collateralized: balance.0 < liabilities,
+        };
+        TokenEvent::Reconcile(&event).emit();
+        if event.is_under_collateralized && self.paused_tokens.insert(&token_id) {
+            log!(
+                "Token '{}' is paused since the contract is under-collateralized.",
+                token_id
+            );
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::contract_interfaces::OwnerAction;
+    use crate::conversion_pool::ConversionPool;
+    use crate::test::{setup_contract, usdc, usdt};
+    use crate::token_receiver::{ConvertAction, TransferMessage, TransferOrigin};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::test_env::bob;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    #[test]
+    fn test_token_liabilities() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        let pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        contract.pools.insert(&U64(1), &pool.into());
+
+        testing_env!(context.predecessor_account_id(usdt()).build());
+        let msg =
+            serde_json::to_string(&TransferMessage::AddLiquidity { pool_id: U64(1) }).unwrap();
+        contract.ft_on_transfer(bob(), U128(100), msg);
+        assert_eq!(contract.internal_get_liability(&usdt()), 100);
+
+        testing_env!(context.predecessor_account_id(usdc()).build());
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id: U64(1),
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: false,
+                deadline: None,
+            },
+        })
+        .unwrap();
+        contract.ft_on_transfer(bob(), U128(10), msg);
+        assert_eq!(contract.internal_get_liability(&usdc()), 10);
+        assert_eq!(contract.internal_get_liability(&usdt()), 90);
+        assert_eq!(contract.internal_get_in_flight_amount(&usdt()), 10);
+
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(10),
+            Some(U64(0)),
+            Some(TransferOrigin::Account),
+        );
+        assert_eq!(contract.internal_get_liability(&usdt()), 100);
+        assert_eq!(contract.internal_get_in_flight_amount(&usdt()), 0);
+    }
+
+    fn reconcile_with_balance(
+        context: &mut VMContextBuilder,
+        contract: &mut TokenConvertor,
+        balance: Balance,
+        liabilities: Balance,
+    ) -> ReconcileEvent {
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(balance)).unwrap()
+            )],
+        );
+        contract.reconcile_resolved(usdt(), U128(liabilities))
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let (mut context, mut contract, _) = setup_contract();
+        contract.internal_increase_liability(&usdt(), 100);
+
+        let event = reconcile_with_balance(&mut context, &mut contract, 100, 100);
+        assert!(!event.is_under_collateralized);
+        assert!(!contract.paused_tokens.contains(&usdt()));
+
+        // liabilities increased after reconciliation started are not compared.
+        let event = reconcile_with_balance(&mut context, &mut contract, 90, 90);
+        assert_eq!(event.liabilities, U128(90));
+        assert!(!event.is_under_collateralized);
+
+        let event = reconcile_with_balance(&mut context, &mut contract, 99, 100);
+        assert!(event.is_under_collateralized);
+        assert!(contract.paused_tokens.contains(&usdt()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pools or accounts of the released version are not migrated yet.")]
+    fn test_recover_surplus_before_migrating_accounts() {
+        let (mut context, mut contract, owner) = setup_contract();
+        // balances of the account of the released version are not in liabilities yet.
+        contract.legacy_accounts_migrated = false;
+        testing_env!(context.predecessor_account_id(owner.clone()).build());
+        contract.recover_surplus(usdt(), owner);
+    }
+
+    #[test]
+    fn test_recover_surplus() {
+        let (mut context, mut contract, _) = setup_contract();
+        contract.internal_increase_liability(&usdt(), 100);
+        contract.internal_increase_in_flight_amount(&usdt(), 10);
+        let mock_balance = |context: &mut VMContextBuilder, balance: Balance| {
+            testing_env!(
+                context
+                    .predecessor_account_id(env::current_account_id())
+                    .build(),
+                VMConfig::test(),
+                RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![PromiseResult::Successful(
+                    serde_json::to_vec(&U128(balance)).unwrap()
+                )],
+            );
+        };
+
+        mock_balance(&mut context, 110);
+        assert_eq!(
+            contract.recover_surplus_resolved(usdt(), bob(), U128(100)),
+            U128(0)
+        );
+
+        // liabilities increased after recovering started are reserved too.
+        mock_balance(&mut context, 150);
+        assert_eq!(
+            contract.recover_surplus_resolved(usdt(), bob(), U128(120)),
+            U128(20)
+        );
+        assert_eq!(contract.internal_get_in_flight_amount(&usdt()), 30);
+        contract.surplus_transfer_resolved(usdt(), U128(20));
+
+        assert_eq!(
+            contract.recover_surplus_resolved(usdt(), bob(), U128(100)),
+            U128(40)
+        );
+        assert_eq!(contract.internal_get_in_flight_amount(&usdt()), 50);
+        // surplus in flight can't be recovered again.
+        assert_eq!(
+            contract.recover_surplus_resolved(usdt(), bob(), U128(100)),
+            U128(0)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token 'usdt' is paused.")]
+    fn test_convert_paused_token() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        contract.internal_increase_liability(&usdt(), 100);
+        reconcile_with_balance(&mut context, &mut contract, 0, 100);
+        contract.internal_convert(U64(1), &bob(), &usdc(), 10);
+    }
+}
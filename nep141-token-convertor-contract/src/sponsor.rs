@@ -0,0 +1,260 @@
+use crate::account::Account;
+use crate::constants::QUOTA_USAGE_STORAGE;
+use crate::contract_interfaces::StorageSponsorAction;
+use crate::*;
+use near_sdk::assert_one_yocto;
+
+impl TokenConvertor {
+    /// register the account with the minimum storage deposit paid by sponsor budget
+    /// of the pool or owner, nothing happens if the account is registered or budgets are not enough.
+    pub(crate) fn internal_sponsor_storage(
+        &mut self,
+        account_id: &AccountId,
+        pool_id: Option<&PoolId>,
+    ) {
+        if self.internal_get_account(account_id).is_some() {
+            return;
+        }
+        let pool = pool_id.and_then(|pool_id| self.internal_get_pool(pool_id));
+        // the quota usage of the account in the pool is also paid.
+        let quota_usage_storage = match pool.as_ref().filter(|pool| pool.user_quota.is_some()) {
+            Some(_) => QUOTA_USAGE_STORAGE as Balance * env::storage_byte_cost(),
+            None => 0,
+        };
+        let amount = self.internal_get_storage_balance_min_bound(account_id) + quota_usage_storage;
+        let mut account = Account::new();
+        if let Some(pool) = pool.filter(|pool| pool.storage_sponsor_budget.0 >= amount) {
+            self.internal_use_pool(pool.id, |pool| {
+                pool.storage_sponsor_budget = U128(pool.storage_sponsor_budget.0 - amount)
+            });
+            log!(
+                "Storage of '{}' is sponsored by pool '{}'.",
+                account_id,
+                pool.id.0
+            );
+            account.storage_sponsor_pool_id = Some(pool.id);
+        } else if self.storage_sponsor_budget >= amount {
+            self.storage_sponsor_budget -= amount;
+            log!("Storage of '{}' is sponsored by owner.", account_id);
+        } else {
+            return;
+        }
+        account.near_amount_for_storage = amount;
+        account.sponsored_storage_amount = amount;
+        self.internal_save_account(account_id, account);
+    }
+
+    fn internal_assert_sponsor_manager(&self, pool_id: &Option<PoolId>) {
+        match pool_id {
+            Some(pool_id) => {
+                let pool = self.internal_get_pool(pool_id).expect("No such pool.");
+                self.internal_assert_pool_manager(&pool);
+            }
+            None => assert_eq!(
+                env::predecessor_account_id(),
+                self.owner,
+                "Only owner can manage the storage sponsor budget of owner."
+            ),
+        }
+    }
+}
+
+#[near_bindgen]
+impl StorageSponsorAction for TokenConvertor {
+    #[payable]
+    fn deposit_storage_sponsor_budget(&mut self, pool_id: Option<PoolId>) {
+        self.assert_contract_is_not_paused();
+        self.internal_assert_sponsor_manager(&pool_id);
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Deposit should be greater than 0.");
+        match pool_id {
+            Some(pool_id) => self.internal_use_pool(pool_id, |pool| {
+                pool.storage_sponsor_budget = U128(pool.storage_sponsor_budget.0 + amount)
+            }),
+            None => self.storage_sponsor_budget += amount,
+        }
+    }
+
+    #[payable]
+    fn withdraw_storage_sponsor_budget(&mut self, pool_id: Option<PoolId>, amount: Option<U128>) {
+        self.assert_contract_is_not_paused();
+        assert_one_yocto();
+        self.internal_assert_sponsor_manager(&pool_id);
+        let budget = match &pool_id {
+            Some(pool_id) => {
+                self.internal_get_pool(pool_id)
+                    .unwrap()
+                    .storage_sponsor_budget
+                    .0
+            }
+            None => self.storage_sponsor_budget,
+        };
+        let amount = amount.map(|e| e.0).unwrap_or(budget);
+        assert!(
+            amount <= budget,
+            "Failed to withdraw. The storage sponsor budget {} is not enough.",
+            budget
+        );
+        match pool_id {
+            Some(pool_id) => self.internal_use_pool(pool_id, |pool| {
+                pool.storage_sponsor_budget = U128(budget - amount)
+            }),
+            None => self.storage_sponsor_budget = budget - amount,
+        }
+        if amount > 0 {
+            self.internal_send_near(env::predecessor_account_id(), amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract_interfaces::ConvertorViewer;
+    use crate::conversion_pool::ConversionPool;
+    use crate::test::{setup_contract, usdc, usdt};
+    use crate::token_receiver::{ConvertAction, TransferMessage};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::testing_env;
+
+    fn convert(contract: &mut TokenConvertor, sender_id: AccountId) {
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id: U64(1),
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: false,
+                deadline: None,
+            },
+        })
+        .unwrap();
+        contract.ft_on_transfer(sender_id, U128(10), msg);
+    }
+
+    #[test]
+    fn test_sponsor_storage() {
+        let (mut context, mut contract, owner) = setup_contract();
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        contract.internal_increase_liability(&usdt(), 100);
+        let min_bound = contract.storage_balance_bounds().min.0;
+
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(min_bound)
+            .build());
+        contract.deposit_storage_sponsor_budget(Some(U64(1)));
+        testing_env!(context
+            .predecessor_account_id(owner)
+            .attached_deposit(min_bound)
+            .build());
+        contract.deposit_storage_sponsor_budget(None);
+
+        testing_env!(context
+            .predecessor_account_id(usdc())
+            .attached_deposit(0)
+            .build());
+        convert(&mut contract, alice());
+        assert_eq!(contract.get_storage_sponsor_budget(Some(U64(1))), U128(0));
+        assert_eq!(contract.get_storage_sponsor_budget(None), U128(min_bound));
+        let account = contract.internal_get_account(&alice()).unwrap();
+        assert_eq!(account.sponsored_storage_amount, min_bound);
+        assert_eq!(account.storage_sponsor_pool_id, Some(U64(1)));
+        assert_eq!(account.available_storage_deposit(), 0);
+
+        let carol = AccountId::new_unchecked("carol.near".to_string());
+        convert(&mut contract, carol.clone());
+        assert_eq!(contract.get_storage_sponsor_budget(None), U128(0));
+
+        // sponsored storage goes back to the budget of owner when unregistering.
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.ft_transfer_resolved(usdt(), carol.clone(), U128(10), Some(U64(0)), None);
+        testing_env!(context
+            .predecessor_account_id(carol)
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+        assert_eq!(contract.get_storage_sponsor_budget(None), U128(min_bound));
+
+        // and to the budget of the pool which sponsored it.
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        contract.ft_transfer_resolved(usdt(), alice(), U128(10), Some(U64(0)), None);
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+        assert_eq!(
+            contract.get_storage_sponsor_budget(Some(U64(1))),
+            U128(min_bound)
+        );
+        assert_eq!(contract.get_storage_sponsor_budget(None), U128(min_bound));
+    }
+
+    #[test]
+    fn test_no_sponsor_when_fully_refunded() {
+        let (mut context, mut contract, _) = setup_contract();
+        let pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        contract.pools.insert(&U64(1), &pool.into());
+        let min_bound = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(min_bound)
+            .build());
+        contract.deposit_storage_sponsor_budget(Some(U64(1)));
+
+        // nothing can be filled by the empty pool, so all of the input is refunded.
+        testing_env!(context
+            .predecessor_account_id(usdc())
+            .attached_deposit(0)
+            .build());
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id: U64(1),
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: true,
+                deadline: None,
+            },
+        })
+        .unwrap();
+        let refunded = contract.ft_on_transfer(alice(), U128(10), msg);
+        assert!(matches!(refunded, PromiseOrValue::Value(U128(10))));
+        assert_eq!(
+            contract.get_storage_sponsor_budget(Some(U64(1))),
+            U128(min_bound)
+        );
+        assert!(contract.internal_get_account(&alice()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "No such account #alice.near.")]
+    fn test_convert_without_sponsor() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        testing_env!(context.predecessor_account_id(usdc()).build());
+        convert(&mut contract, alice());
+    }
+}
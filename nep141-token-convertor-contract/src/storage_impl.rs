@@ -1,5 +1,7 @@
 use crate::account::Account;
 use crate::constants::{PREPAY_STORAGE_FOR_REGISTERED, PREPAY_STORAGE_FOR_UNREGISTERED};
+use crate::events::{AccountEvent, EventEmit};
+use crate::token_receiver::TransferOrigin;
 use crate::*;
 use near_contract_standards::storage_management::{
     StorageBalance, StorageBalanceBounds, StorageManagement,
@@ -20,9 +22,7 @@ impl StorageManagement for TokenConvertor {
         self.assert_contract_is_not_paused();
         let attach_amount = env::attached_deposit();
         let account_id = account_id.unwrap_or(env::predecessor_account_id());
-        let mut account = self
-            .internal_get_account(&account_id)
-            .unwrap_or(Account::new());
+        let mut account = self.internal_get_account(&account_id).unwrap_or_default();
         let registration_only = registration_only.unwrap_or(false);
         let min_balance = self.internal_get_storage_balance_min_bound(&account_id);
         log!(
@@ -78,29 +78,36 @@ impl StorageManagement for TokenConvertor {
             .unwrap();
     }
 
-    #[allow(unused_variables)]
+    /// if force is true, the remaining tokens are sent to the account, or recorded as its lost and found
+    /// if the storage deposit can't pay the transfer, and the account is deleted
+    /// after all of its transfers are resolved.
     #[payable]
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         self.assert_contract_is_not_paused();
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
+        let account = match self.internal_get_account(&account_id) {
+            Some(account) => account,
+            None => return false,
+        };
+        assert!(
+            !account.is_unregistering,
+            "The account is already being unregistered."
+        );
+        if force.unwrap_or(false) {
+            self.internal_force_unregister(&account_id, account);
+            return true;
+        }
         assert!(
             !self.internal_check_ft_transfer_is_lock(&account_id),
             "Can not unregister while 'ft_transfer' is still in processing."
         );
-        if let Some(account) = self.internal_get_account(&account_id) {
-            assert!(
-                account.tokens.is_empty(),
-                "Can not unregister if the account is still holding token(s)."
-            );
-            self.accounts.remove(&account_id);
-            if account.near_amount_for_storage > 0 {
-                Promise::new(account_id.clone()).transfer(account.near_amount_for_storage);
-            }
-            true
-        } else {
-            false
-        }
+        assert!(
+            account.tokens.is_empty(),
+            "Can not unregister if the account is still holding token(s)."
+        );
+        self.internal_remove_account(&account_id, account);
+        true
     }
 
     fn storage_balance_bounds(&self) -> StorageBalanceBounds {
@@ -123,13 +130,432 @@ impl StorageManagement for TokenConvertor {
 
 impl TokenConvertor {
     pub(crate) fn internal_get_storage_balance_min_bound(&self, account_id: &AccountId) -> u128 {
-        let account = self.internal_get_account(account_id);
-        let min_usage = if account.is_some() {
+        let min_usage = match self.internal_get_account(account_id) {
             // besides actually usage, need to add maximum storage cost of all change methods
-            account.unwrap().storage_usage() + PREPAY_STORAGE_FOR_REGISTERED
-        } else {
-            PREPAY_STORAGE_FOR_UNREGISTERED
+            Some(account) => account.storage_usage + PREPAY_STORAGE_FOR_REGISTERED,
+            None => PREPAY_STORAGE_FOR_UNREGISTERED,
         };
         return min_usage as u128 * env::storage_byte_cost();
     }
+
+    fn internal_force_unregister(&mut self, account_id: &AccountId, mut account: Account) {
+        AccountEvent::StartUnregister { account_id }.emit();
+        let tokens = account.tokens.drain().collect_vec();
+        account.is_unregistering = true;
+        self.internal_save_account(account_id, account);
+        for (token_id, amount) in tokens {
+            if amount == 0 {
+                continue;
+            }
+            let account = self.internal_get_account(account_id).unwrap();
+            if account.near_amount_for_storage
+                >= self.internal_get_storage_balance_min_bound(account_id)
+            {
+                self.internal_send_tokens(account_id, &token_id, amount, TransferOrigin::Account);
+                AccountEvent::SweepToken {
+                    account_id,
+                    token_id: &token_id,
+                    amount: &U128(amount),
+                }
+                .emit();
+            } else {
+                // the account can claim it after registering again.
+                self.internal_record_lost_found(account_id, &token_id, amount);
+                AccountEvent::SweepTokenToLostFound {
+                    account_id,
+                    token_id: &token_id,
+                    amount: &U128(amount),
+                }
+                .emit();
+            }
+        }
+        self.internal_finish_unregister(account_id);
+    }
+
+    /// delete the account being unregistered if all of its transfers are resolved.
+    pub(crate) fn internal_finish_unregister(&mut self, account_id: &AccountId) {
+        if let Some(account) = self.internal_get_account(account_id) {
+            if account.is_unregistering && account.ft_transfer_lock == 0 {
+                self.internal_remove_account(account_id, account);
+            }
+        }
+    }
+
+    fn internal_remove_account(&mut self, account_id: &AccountId, account: Account) {
+        assert!(
+            account.tokens.is_empty(),
+            "Can not unregister if the account is still holding token(s)."
+        );
+        self.accounts.remove(account_id);
+        for pool_id in &account.quota_pool_ids {
+            self.internal_remove_quota_usage(pool_id, account_id);
+        }
+        // sponsored storage goes back to the budget of its sponsor,
+        // or of owner if the sponsoring pool is deleted.
+        match account
+            .storage_sponsor_pool_id
+            .filter(|pool_id| self.internal_get_pool(pool_id).is_some())
+        {
+            Some(pool_id) => self.internal_use_pool(pool_id, |pool| {
+                pool.storage_sponsor_budget =
+                    U128(pool.storage_sponsor_budget.0 + account.sponsored_storage_amount)
+            }),
+            None => self.storage_sponsor_budget += account.sponsored_storage_amount,
+        }
+        let refund_amount = account.near_amount_for_storage - account.sponsored_storage_amount;
+        if refund_amount > 0 {
+            Promise::new(account_id.clone()).transfer(refund_amount);
+        }
+        AccountEvent::Unregister {
+            account_id,
+            refunded_amount: &U128(refund_amount),
+        }
+        .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::VAccount;
+    use crate::constants::{INIT_ACCOUNT_STORAGE, INIT_ACCOUNT_V1_STORAGE};
+    use crate::contract_interfaces::ConvertorViewer;
+    use crate::contract_interfaces::{AccountAction, PoolAllowlistAction, PoolCreatorAction};
+    use crate::legacy::AccountV1;
+    use crate::test::{setup_contract, usdc, usdt, whitelist_tokens};
+    use crate::token_receiver::{ConvertAction, TransferMessage};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::test_env::{alice, bob};
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    fn register_with_tokens(contract: &mut TokenConvertor, near_amount_for_storage: Balance) {
+        let mut account = Account::new();
+        account.near_amount_for_storage = near_amount_for_storage;
+        account.deposit_token(&usdc(), 10);
+        account.deposit_token(&usdt(), 20);
+        contract.internal_save_account(&alice(), account);
+        contract.internal_increase_liability(&usdc(), 10);
+        contract.internal_increase_liability(&usdt(), 20);
+    }
+
+    fn resolve(
+        context: &mut VMContextBuilder,
+        contract: &mut TokenConvertor,
+        token_id: AccountId,
+        amount: Balance,
+        nonce: u64,
+        result: PromiseResult,
+    ) {
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .storage_usage(env::storage_usage())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+        contract.ft_transfer_resolved(token_id, alice(), U128(amount), Some(U64(nonce)), None);
+    }
+
+    #[test]
+    fn test_force_unregister() {
+        let (mut context, mut contract, _) = setup_contract();
+        register_with_tokens(&mut contract, 10u128.pow(24));
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        let logs = get_logs();
+        assert!(logs[0].contains(r#""account_event":"start_unregister""#));
+        assert_eq!(
+            logs.iter()
+                .filter(|log| log.contains(r#""account_event":"sweep_token""#))
+                .count(),
+            2
+        );
+        let account = contract.internal_get_account(&alice()).unwrap();
+        assert!(account.is_unregistering);
+        assert!(account.tokens.is_empty());
+        assert_eq!(account.in_flight_transfers.len(), 2);
+
+        let nonce = |contract: &TokenConvertor, token_id: AccountId| {
+            contract
+                .get_in_flight_transfers(alice())
+                .into_iter()
+                .find(|transfer| transfer.token_id == token_id)
+                .unwrap()
+                .nonce
+                .0
+        };
+        let usdc_nonce = nonce(&contract, usdc());
+        let usdt_nonce = nonce(&contract, usdt());
+        resolve(
+            &mut context,
+            &mut contract,
+            usdc(),
+            10,
+            usdc_nonce,
+            PromiseResult::Successful(vec![]),
+        );
+        assert!(contract.internal_get_account(&alice()).is_some());
+        // the failed transfer is recorded as lost and found of the account.
+        resolve(
+            &mut context,
+            &mut contract,
+            usdt(),
+            20,
+            usdt_nonce,
+            PromiseResult::Failed,
+        );
+        assert!(contract.internal_get_account(&alice()).is_none());
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains(r#""account_event":"unregister""#)));
+        assert_eq!(
+            contract.get_lost_found(alice()).get(&usdt()),
+            Some(&U128(20))
+        );
+        assert_eq!(contract.get_token_liabilities(usdt()), U128(20));
+        assert_eq!(contract.get_token_in_flight_amount(usdt()), U128(0));
+    }
+
+    #[test]
+    fn test_force_unregister_without_storage_for_transfers() {
+        let (mut context, mut contract, _) = setup_contract();
+        register_with_tokens(&mut contract, 10u128.pow(24));
+        // the storage deposit only pays the storage used by the account.
+        let mut account = contract.internal_get_account(&alice()).unwrap();
+        account.near_amount_for_storage = account.storage_cost();
+        contract.internal_save_account(&alice(), account);
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(contract.internal_get_account(&alice()).is_none());
+        assert_eq!(
+            get_logs()
+                .iter()
+                .filter(|log| log.contains(r#""account_event":"sweep_token_to_lost_found""#))
+                .count(),
+            2
+        );
+        let lost_found = contract.get_lost_found(alice());
+        assert_eq!(lost_found.get(&usdc()), Some(&U128(10)));
+        assert_eq!(lost_found.get(&usdt()), Some(&U128(20)));
+
+        // the swept tokens can be claimed after registering again.
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.claim_lost_found(usdc());
+        assert_eq!(
+            contract.get_account(alice()).tokens,
+            HashMap::from([(usdc(), U128(10))])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The account is being unregistered, no token can be deposited.")]
+    fn test_claim_lost_found_while_unregistering() {
+        let (mut context, mut contract, _) = setup_contract();
+        register_with_tokens(&mut contract, 10u128.pow(24));
+        contract.internal_record_lost_found(&alice(), &usdc(), 5);
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(Some(true));
+        assert!(
+            contract
+                .internal_get_account(&alice())
+                .unwrap()
+                .is_unregistering
+        );
+        contract.claim_lost_found(usdc());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not unregister if the account is still holding token(s).")]
+    fn test_unregister_without_force() {
+        let (mut context, mut contract, _) = setup_contract();
+        register_with_tokens(&mut contract, 10u128.pow(24));
+        testing_env!(context
+            .predecessor_account_id(alice())
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_measure_account_storage() {
+        let (mut context, mut contract, _) = setup_contract();
+        let initial_storage_usage = env::storage_usage();
+        let min_bound = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(alice())
+            .attached_deposit(min_bound)
+            .build());
+        contract.storage_deposit(None, Some(true));
+        let account = contract.internal_get_account(&alice()).unwrap();
+        assert_eq!(
+            account.storage_usage,
+            env::storage_usage() - initial_storage_usage
+        );
+        assert!(account.storage_usage <= INIT_ACCOUNT_STORAGE);
+    }
+
+    #[test]
+    fn test_migrate_min_deposit_account() {
+        let (mut context, mut contract, _) = setup_contract();
+        // the minimum storage deposit of the released version.
+        let v1_min_deposit = (INIT_ACCOUNT_V1_STORAGE + PREPAY_STORAGE_FOR_REGISTERED) as Balance
+            * env::storage_byte_cost();
+        contract.accounts.insert(
+            &alice(),
+            &VAccount::V1(AccountV1 {
+                near_amount_for_storage: v1_min_deposit,
+                tokens: HashMap::from([(usdc(), 10)]),
+                ft_transfer_lock: 0,
+            }),
+        );
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        assert_eq!(contract.migrate_accounts(vec![alice()]), 1);
+        let account = contract.internal_get_account(&alice()).unwrap();
+        assert!(account.storage_cost() <= account.near_amount_for_storage);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(alice())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_account(usdc());
+        resolve(
+            &mut context,
+            &mut contract,
+            usdc(),
+            10,
+            0,
+            PromiseResult::Successful(vec![]),
+        );
+        let account = contract.internal_get_account(&alice()).unwrap();
+        assert!(account.tokens.is_empty());
+        assert_eq!(account.storage_usage, INIT_ACCOUNT_V1_STORAGE);
+        assert_eq!(contract.get_token_liabilities(usdc()), U128(0));
+    }
+
+    /// storage usage of the mocked blockchain is kept between calls.
+    /// storage used by accounts and pools is paid by their deposits,
+    /// so the contract never goes below its storage-staked balance.
+    #[test]
+    fn test_storage_paid_by_accounts_and_pools() {
+        let (mut context, mut contract, _) = setup_contract();
+        whitelist_tokens(&mut contract);
+        contract.create_pool_deposit = 10u128.pow(24);
+        // ledgers of whitelisted tokens are kept by the contract itself.
+        contract.internal_increase_liability(&usdc(), 1);
+        contract.internal_increase_liability(&usdt(), 100);
+        contract.internal_increase_in_flight_amount(&usdt(), 1);
+        let initial_storage_usage = env::storage_usage();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(bob())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let pool_id = contract.create_pool(usdc(), usdt(), false, 1, 1, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.set_pool_private(pool_id, true);
+        contract.add_accounts_to_pool_allowlist(pool_id, vec![alice(), bob()]);
+        contract.set_pool_user_quota(pool_id, Some((U64(1000), U128(50))));
+        contract.internal_use_pool(pool_id, |pool| pool.out_token_balance = U128(100));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(alice())
+            .attached_deposit(10u128.pow(22))
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(usdc())
+            .attached_deposit(0)
+            .build());
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id,
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: false,
+                deadline: None,
+            },
+        })
+        .unwrap();
+        contract.ft_on_transfer(alice(), U128(10), msg);
+
+        let account = contract.internal_get_account(&alice()).unwrap();
+        let pool = contract.internal_get_pool(&pool_id).unwrap();
+        assert_eq!(account.in_flight_transfers.len(), 1);
+        assert_eq!(account.quota_pool_ids, vec![pool_id]);
+        assert_eq!(
+            account.storage_usage + pool.storage_usage.0,
+            env::storage_usage() - initial_storage_usage
+        );
+        assert!(account.storage_cost() <= account.near_amount_for_storage);
+        assert!(pool.storage_cost() <= pool.deposit_near_amount.0);
+
+        // quota usage of the account is removed with the account.
+        resolve(
+            &mut context,
+            &mut contract,
+            usdt(),
+            10,
+            0,
+            PromiseResult::Successful(vec![]),
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(alice())
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+        assert!(contract.quota_usages.get(&(pool_id, alice())).is_none());
+        assert!(contract.pool_quota_accounts.get(&pool_id).is_none());
+        assert_eq!(
+            pool.storage_usage.0,
+            env::storage_usage() - initial_storage_usage
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Creating a pool requires a deposit of")]
+    fn test_pool_storage_not_paid() {
+        let (mut context, mut contract, _) = setup_contract();
+        whitelist_tokens(&mut contract);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(bob())
+            .attached_deposit(0)
+            .build());
+        contract.create_pool(usdc(), usdt(), false, 1, 1, None);
+    }
 }
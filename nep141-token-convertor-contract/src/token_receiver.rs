@@ -1,9 +1,9 @@
-use crate::account::Account;
 use crate::constants::{T_GAS_FOR_FT_TRANSFER, T_GAS_FOR_RESOLVE_TRANSFER};
 use crate::events::{EventEmit, PoolEvent};
 use crate::*;
 use near_contract_standards::fungible_token::core::ext_ft_core;
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U64;
 use near_sdk::ONE_YOCTO;
 use std::ops::Mul;
 
@@ -16,13 +16,47 @@ pub struct ConvertAction {
     pub pool_id: PoolId,
     pub input_token_id: AccountId,
     pub input_token_amount: U128,
+    // if allow_partial is true and the pool can't convert all of the input token,
+    // the pool converts as much as it can and the rest will be refunded.
+    #[serde(default)]
+    pub allow_partial: bool,
+    // block timestamp in nanoseconds, if the transfer is received after it,
+    // all of the input token will be refunded.
+    #[serde(default)]
+    pub deadline: Option<U64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConvertLeg {
+    pub pool_id: PoolId,
+    pub amount: U128,
+}
+
+// user convert a type of token into another across several pools,
+// outputs of all pools will be sent to user together.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConvertSplitAction {
+    pub input_token_id: AccountId,
+    pub input_token_amount: U128,
+    pub output_token_id: AccountId,
+    // if legs is none, contract will split the amount into pools by best rate.
+    pub legs: Option<Vec<ConvertLeg>>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub enum TransferMessage {
-    AddLiquidity { pool_id: PoolId },
-    Convert { convert_action: ConvertAction },
+    AddLiquidity {
+        pool_id: PoolId,
+    },
+    Convert {
+        convert_action: ConvertAction,
+    },
+    ConvertSplit {
+        convert_split_action: ConvertSplitAction,
+    },
 }
 
 #[near_bindgen]
@@ -40,8 +74,10 @@ impl FungibleTokenReceiver for TokenConvertor {
         let transfer_message: TransferMessage =
             serde_json::from_str(msg.as_str()).expect("Invalid parameter 'msg' is attached.");
         let token_id = env::predecessor_account_id();
-        match transfer_message {
+        let unused_amount = match transfer_message {
             TransferMessage::AddLiquidity { pool_id } => {
+                self.assert_token_is_not_delisted(&token_id);
+                self.internal_increase_liability(&token_id, amount.0);
                 self.internal_use_pool(pool_id, |pool| {
                     assert_eq!(
                         sender_id, pool.creator,
@@ -53,6 +89,7 @@ impl FungibleTokenReceiver for TokenConvertor {
                     pool: self.internal_get_pool(&pool_id).as_ref().unwrap(),
                 }
                 .emit();
+                0
             }
             TransferMessage::Convert { convert_action } => {
                 assert_eq!(
@@ -65,17 +102,157 @@ impl FungibleTokenReceiver for TokenConvertor {
                     "Received amount '{}' does not match the amount '{}' specified in attached 'msg'.",
                     amount.0, convert_action.input_token_amount.0
                 );
-                let (receive_token_id, receive_token_amount) =
-                    self.internal_convert(convert_action.pool_id, &token_id, amount.0);
+                if let Some(deadline) = convert_action.deadline {
+                    if env::block_timestamp() > deadline.0 {
+                        log!(
+                            "The deadline {} of conversion has passed, all of the input token will be refunded.",
+                            deadline.0
+                        );
+                        return PromiseOrValue::Value(amount);
+                    }
+                }
+                let filled_amount = if convert_action.allow_partial {
+                    let fillable_amount = self
+                        .internal_get_pool(&convert_action.pool_id)
+                        .expect("No such pool.")
+                        .get_fillable_input_amount(&token_id, amount.0);
+                    self.internal_get_remaining_quota(&convert_action.pool_id, &sender_id)
+                        .map(|quota| std::cmp::min(quota, fillable_amount))
+                        .unwrap_or(fillable_amount)
+                } else {
+                    amount.0
+                };
+                if filled_amount == 0 {
+                    log!(
+                        "Pool '{}' can't convert any of the input token, all of it will be refunded.",
+                        convert_action.pool_id.0
+                    );
+                    return PromiseOrValue::Value(amount);
+                }
+                // only sponsored when something is converted, so a full refund costs no budget.
+                self.internal_sponsor_storage(&sender_id, Some(&convert_action.pool_id));
+                let refunded_amount = amount.0 - filled_amount;
+                // the received token is a liability before the pool forwards any of it.
+                self.internal_increase_liability(&token_id, filled_amount);
+                let (receive_token_id, receive_token_amount) = self.internal_convert(
+                    convert_action.pool_id,
+                    &sender_id,
+                    &token_id,
+                    filled_amount,
+                );
                 let pool = self.internal_get_pool(&convert_action.pool_id).unwrap();
                 PoolEvent::UpdatePool { pool: &pool }.emit();
-                self.internal_send_tokens(&sender_id, &receive_token_id, receive_token_amount);
+                PoolEvent::Convert {
+                    pool_id: &convert_action.pool_id,
+                    account_id: &sender_id,
+                    input_token_id: &token_id,
+                    filled_amount: &U128(filled_amount),
+                    refunded_amount: &U128(refunded_amount),
+                    output_token_id: &receive_token_id,
+                    output_amount: &U128(receive_token_amount),
+                }
+                .emit();
+                self.internal_use_account(&sender_id, |account| account.conversion_count += 1);
+                self.internal_send_tokens(
+                    &sender_id,
+                    &receive_token_id,
+                    receive_token_amount,
+                    TransferOrigin::Conversion,
+                );
+                refunded_amount
             }
-        }
-        PromiseOrValue::Value(U128(0))
+            TransferMessage::ConvertSplit {
+                convert_split_action,
+            } => {
+                assert_eq!(
+                    token_id, convert_split_action.input_token_id,
+                    "Received token '{}' does not match the token '{}' specified in attached 'msg'.",
+                    token_id, convert_split_action.input_token_id
+                );
+                assert_eq!(
+                    amount, convert_split_action.input_token_amount,
+                    "Received amount '{}' does not match the amount '{}' specified in attached 'msg'.",
+                    amount.0, convert_split_action.input_token_amount.0
+                );
+                self.internal_sponsor_storage(&sender_id, None);
+                let output_token_id = convert_split_action.output_token_id;
+                let legs = match convert_split_action.legs {
+                    Some(legs) => {
+                        let legs = legs
+                            .into_iter()
+                            .map(|leg| (leg.pool_id, leg.amount.0))
+                            .collect_vec();
+                        let total_amount = legs.iter().fold(0u128, |total, (_, amount)| {
+                            total
+                                .checked_add(*amount)
+                                .expect("Total amount of legs overflowed.")
+                        });
+                        assert_eq!(
+                            total_amount, amount.0,
+                            "Total amount '{}' of legs does not match the received amount '{}'.",
+                            total_amount, amount.0
+                        );
+                        legs
+                    }
+                    None => self.internal_plan_split_conversion(
+                        &token_id,
+                        &output_token_id,
+                        amount.0,
+                        &sender_id,
+                    ),
+                };
+                self.internal_increase_liability(&token_id, amount.0);
+                let mut receive_token_amount: Balance = 0;
+                for (pool_id, leg_amount) in legs {
+                    assert!(leg_amount > 0, "Amount of leg should be greater than 0.");
+                    let (receive_token_id, leg_receive_amount) =
+                        self.internal_convert(pool_id, &sender_id, &token_id, leg_amount);
+                    assert_eq!(
+                        receive_token_id, output_token_id,
+                        "Pool '{}' converts into '{}' instead of '{}'.",
+                        pool_id.0, receive_token_id, output_token_id
+                    );
+                    receive_token_amount += leg_receive_amount;
+                    let pool = self.internal_get_pool(&pool_id).unwrap();
+                    PoolEvent::UpdatePool { pool: &pool }.emit();
+                    PoolEvent::Convert {
+                        pool_id: &pool_id,
+                        account_id: &sender_id,
+                        input_token_id: &token_id,
+                        filled_amount: &U128(leg_amount),
+                        refunded_amount: &U128(0),
+                        output_token_id: &output_token_id,
+                        output_amount: &U128(leg_receive_amount),
+                    }
+                    .emit();
+                }
+                self.internal_use_account(&sender_id, |account| account.conversion_count += 1);
+                self.internal_send_tokens(
+                    &sender_id,
+                    &output_token_id,
+                    receive_token_amount,
+                    TransferOrigin::Conversion,
+                );
+                0
+            }
+        };
+        PromiseOrValue::Value(U128(unused_amount))
     }
 }
 
+/// where the tokens sent out of the contract come from,
+/// the tokens are restored to it if the transfer fails.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TransferOrigin {
+    /// withdrawn from the balance of the receiver's account
+    Account,
+    /// output of conversions in pools
+    Conversion,
+    /// withdrawn from a pool by its creator or owner, the pool is locked until the transfer is resolved
+    PoolWithdrawal { pool_id: PoolId },
+}
+
 #[near_bindgen]
 impl TokenConvertor {
     pub(crate) fn internal_send_near(&self, receiver_id: AccountId, amount: Balance) -> Promise {
@@ -87,11 +264,16 @@ impl TokenConvertor {
         receiver_id: &AccountId,
         token_id: &AccountId,
         amount: Balance,
+        origin: TransferOrigin,
     ) -> Promise {
         self.assert_storage_balance_bound_min(receiver_id);
-        // account ft_transfer_lock plus one, it'll minus one when ft_transfer_resolved,
+        // record the transfer and plus account ft_transfer_lock one, they'll be cleared when ft_transfer_resolved,
         // By this way, contract can avoid some methods executing between ft_transfer and ft_transfer_resolved
-        self.internal_use_account(&receiver_id, |account| account.plus_ft_transfer_lock());
+        let nonce = self.internal_use_account(receiver_id, |account| {
+            account.start_transfer(token_id, amount)
+        });
+        self.internal_decrease_liability(token_id, amount);
+        self.internal_increase_in_flight_amount(token_id, amount);
 
         ext_ft_core::ext(token_id.clone())
             .with_attached_deposit(ONE_YOCTO)
@@ -100,42 +282,309 @@ impl TokenConvertor {
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(Gas::ONE_TERA.mul(T_GAS_FOR_RESOLVE_TRANSFER))
-                    .ft_transfer_resolved(token_id.clone(), receiver_id.clone(), U128(amount)),
+                    .ft_transfer_resolved(
+                        token_id.clone(),
+                        receiver_id.clone(),
+                        U128(amount),
+                        Some(U64(nonce)),
+                        Some(origin),
+                    ),
             )
     }
 
+    /// nonce and origin are none for transfers started by the released version,
+    /// which were withdrawn from accounts.
     #[private]
     pub fn ft_transfer_resolved(
         &mut self,
         token_id: AccountId,
         sender_id: AccountId,
         amount: U128,
+        nonce: Option<U64>,
+        origin: Option<TransferOrigin>,
     ) {
         assert_eq!(
             env::promise_results_count(),
             1,
             "Expect 1 promise result for sending token."
         );
+        let nonce = nonce.map(|nonce| nonce.0);
+        let origin = origin.unwrap_or(TransferOrigin::Account);
+        // transfers of the released version were not counted as in flight.
+        if nonce.is_some() {
+            self.internal_decrease_in_flight_amount(&token_id, amount.0);
+        }
+        if self.internal_get_account(&sender_id).is_some() {
+            self.internal_use_account(&sender_id, |account| account.finish_transfer(nonce));
+        }
+        let pool_id = match &origin {
+            TransferOrigin::PoolWithdrawal { pool_id } => {
+                self.internal_get_pool(pool_id).map(|_| *pool_id)
+            }
+            _ => None,
+        };
+        if let Some(pool_id) = pool_id {
+            self.internal_use_pool(pool_id, |pool| pool.unlock());
+        }
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(_) => {
-                self.internal_use_account(&sender_id, |account| account.minus_ft_transfer_lock());
-            }
+            PromiseResult::Successful(_) => {}
             PromiseResult::Failed => {
                 // This reverts the changes from withdraw function.
-                // If account doesn't exit, deposits to the owner's account as lostfound.
                 log!(
                     "Failed to transfer token '{}' for '{}'. Try to register the account in the token contract first.",
                     token_id,
                     sender_id
                 );
-                let mut account = self
-                    .internal_get_account(&sender_id)
-                    .unwrap_or(Account::new());
-                account.deposit_token(&token_id, amount.0);
-                account.minus_ft_transfer_lock();
-                self.internal_save_account(&sender_id, account);
+                self.internal_increase_liability(&token_id, amount.0);
+                match pool_id {
+                    Some(pool_id) => {
+                        self.internal_use_pool(pool_id, |pool| {
+                            pool.restore_token(&token_id, amount.0)
+                        });
+                        log!(
+                            "{} of token '{}' is restored to pool '{}'.",
+                            amount.0,
+                            token_id,
+                            pool_id.0
+                        );
+                    }
+                    None => self.internal_credit_account(&sender_id, &token_id, amount.0),
+                }
+            }
+        };
+        self.internal_finish_unregister(&sender_id);
+    }
+
+    #[private]
+    pub fn in_token_forward_resolved(
+        &mut self,
+        pool_id: PoolId,
+        token_id: AccountId,
+        sink_id: AccountId,
+        amount: U128,
+    ) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expect 1 promise result for forwarding token."
+        );
+        self.internal_decrease_in_flight_amount(&token_id, amount.0);
+        if self.internal_get_pool(&pool_id).is_some() {
+            self.internal_use_pool(pool_id, |pool| pool.unlock());
+        }
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {}
+            PromiseResult::Failed => {
+                // The token is kept in the pool and will be forwarded with the next conversion.
+                // If the pool has been deleted, deposits to the sink's account.
+                log!(
+                    "Failed to forward token '{}' of pool '{}' to '{}'.",
+                    token_id,
+                    pool_id.0,
+                    sink_id
+                );
+                self.internal_increase_liability(&token_id, amount.0);
+                match self.internal_get_pool(&pool_id) {
+                    Some(_) => self.internal_use_pool(pool_id, |pool| {
+                        pool.in_token_balance = U128(pool.in_token_balance.0 + amount.0)
+                    }),
+                    None => self.internal_credit_account(&sink_id, &token_id, amount.0),
+                }
             }
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Account;
+    use crate::contract_interfaces::{AccountAction, ConvertorViewer, PoolCreatorAction};
+    use crate::conversion_pool::ConversionPool;
+    use crate::test::{setup_contract, usdc, usdt};
+    use near_sdk::test_utils::test_env::bob;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_convert_after_deadline() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        testing_env!(context.predecessor_account_id(usdc()).build());
+        let msg = serde_json::to_string(&TransferMessage::Convert {
+            convert_action: ConvertAction {
+                pool_id: U64(1),
+                input_token_id: usdc(),
+                input_token_amount: U128(10),
+                allow_partial: false,
+                deadline: Some(U64(env::block_timestamp() - 1)),
+            },
+        })
+        .unwrap();
+        match contract.ft_on_transfer(bob(), U128(10), msg) {
+            PromiseOrValue::Value(refunded_amount) => assert_eq!(refunded_amount, U128(10)),
+            PromiseOrValue::Promise(_) => panic!("Expected the full amount to be refunded."),
+        }
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.out_token_balance, U128(100));
+    }
+
+    #[test]
+    fn test_in_flight_transfers() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        contract.internal_increase_liability(&usdt(), 30);
+        contract.internal_send_tokens(&bob(), &usdt(), 10, TransferOrigin::Account);
+        contract.internal_send_tokens(&bob(), &usdt(), 20, TransferOrigin::Account);
+        let transfers = contract.get_in_flight_transfers(bob());
+        assert_eq!(
+            transfers
+                .iter()
+                .map(|e| (e.nonce.0, e.amount.0))
+                .collect_vec(),
+            vec![(0, 10), (1, 20)]
+        );
+        assert!(contract.internal_check_ft_transfer_is_lock(&bob()));
+
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(20),
+            Some(U64(1)),
+            Some(TransferOrigin::Account),
+        );
+        let transfers = contract.get_in_flight_transfers(bob());
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].nonce, U64(0));
+
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(10),
+            Some(U64(0)),
+            Some(TransferOrigin::Account),
+        );
+        assert!(contract.get_in_flight_transfers(bob()).is_empty());
+        assert!(!contract.internal_check_ft_transfer_is_lock(&bob()));
+    }
+
+    fn resolve_failed(context: &mut VMContextBuilder) {
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+    }
+
+    #[test]
+    fn test_failed_pool_withdrawal_restored_to_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.out_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        contract.internal_increase_liability(&usdt(), 100);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdt(), None);
+        assert_eq!(contract.internal_get_liability(&usdt()), 0);
+
+        resolve_failed(&mut context);
+        contract.ft_transfer_resolved(
+            usdt(),
+            bob(),
+            U128(100),
+            Some(U64(0)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.out_token_balance, U128(100));
+        assert_eq!(pool.pending_transfer_count, 0);
+        assert!(contract.get_account(bob()).tokens.is_empty());
+        assert!(contract.get_in_flight_transfers(bob()).is_empty());
+        assert_eq!(contract.internal_get_liability(&usdt()), 100);
+    }
+
+    #[test]
+    fn test_failed_in_token_withdrawal_restored_to_pool() {
+        let (mut context, mut contract, _) = setup_contract();
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        // the in_token of a non-reversible pool can't be added as liquidity.
+        let mut pool = ConversionPool::new(U64(1), bob(), usdc(), usdt(), false, 1, 1, U128(0));
+        pool.in_token_balance = U128(100);
+        contract.pools.insert(&U64(1), &pool.into());
+        contract.internal_increase_liability(&usdc(), 100);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.withdraw_token_in_pool(U64(1), usdc(), None);
+
+        resolve_failed(&mut context);
+        contract.ft_transfer_resolved(
+            usdc(),
+            bob(),
+            U128(100),
+            Some(U64(0)),
+            Some(TransferOrigin::PoolWithdrawal { pool_id: U64(1) }),
+        );
+        let pool = contract.internal_get_pool(&U64(1)).unwrap();
+        assert_eq!(pool.in_token_balance, U128(100));
+        assert_eq!(pool.pending_transfer_count, 0);
+        assert_eq!(contract.get_token_in_flight_amount(usdc()), U128(0));
+        assert_eq!(contract.internal_get_liability(&usdc()), 100);
+    }
+
+    #[test]
+    fn test_failed_transfer_to_unregistered_account() {
+        let (mut context, mut contract, _) = setup_contract();
+        // the released version didn't record nonce and origin of transfers.
+        resolve_failed(&mut context);
+        contract.ft_transfer_resolved(usdt(), bob(), U128(10), None, None);
+        assert_eq!(
+            contract.get_lost_found(bob()),
+            HashMap::from([(usdt(), U128(10))])
+        );
+        assert_eq!(contract.internal_get_liability(&usdt()), 10);
+
+        let mut account = Account::new();
+        account.near_amount_for_storage = 10u128.pow(24);
+        contract.internal_save_account(&bob(), account);
+        testing_env!(context
+            .predecessor_account_id(bob())
+            .attached_deposit(1)
+            .build());
+        contract.claim_lost_found(usdt());
+        assert!(contract.get_lost_found(bob()).is_empty());
+        assert_eq!(
+            contract.get_account(bob()).tokens,
+            HashMap::from([(usdt(), U128(10))])
+        );
+    }
+}
@@ -1,14 +1,22 @@
 use crate::*;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_sdk::json_types::U64;
 use near_sdk::AccountId;
 use uint::construct_uint;
 
 pub type PoolId = U64;
 
-construct_uint! {
-    /// 256-bit unsigned integer.
-    pub struct U256(4);
+// code generated by construct_uint! trips some lints which can't be fixed here.
+#[allow(clippy::manual_div_ceil, clippy::assign_op_pattern)]
+mod uint_types {
+    use super::construct_uint;
+
+    construct_uint! {
+        /// 256-bit unsigned integer.
+        pub struct U256(4);
+    }
 }
+pub use uint_types::U256;
 
 #[derive(
     BorshDeserialize,
@@ -25,4 +33,26 @@ construct_uint! {
 pub struct FtMetaData {
     pub token_id: AccountId,
     pub decimals: u8,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    /// link to a json file with more info of the token
+    pub reference: Option<String>,
+    /// free-form labels set by owner, e.g. "stablecoin", "bridged"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl FtMetaData {
+    pub fn from_metadata(token_id: AccountId, metadata: FungibleTokenMetadata) -> Self {
+        Self {
+            token_id,
+            decimals: metadata.decimals,
+            symbol: Some(metadata.symbol),
+            name: Some(metadata.name),
+            icon: metadata.icon,
+            reference: metadata.reference,
+            tags: vec![],
+        }
+    }
 }
@@ -0,0 +1,299 @@
+use crate::constants::{STAGED_CODE_DELAY_IN_NANOS, STATE_VERSION, T_GAS_FOR_MIGRATE};
+use crate::contract_interfaces::UpgradeAction;
+use crate::legacy::TokenConvertorV1;
+use crate::*;
+use near_contract_standards::upgrade::Ownable;
+use near_sdk::json_types::Base58CryptoHash;
+use near_sdk::IntoStorageKey;
+use std::ops::Mul;
+
+/// none means the state is of the released version.
+fn read_state_version() -> Option<u32> {
+    env::storage_read(&StorageKey::StateVersion.into_storage_key())
+        .map(|version| u32::try_from_slice(&version).expect("Failed to read state version."))
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(
+        &StorageKey::StateVersion.into_storage_key(),
+        &STATE_VERSION.try_to_vec().unwrap(),
+    );
+}
+
+#[near_bindgen]
+impl UpgradeAction for TokenConvertor {
+    fn stage_code(&mut self) {
+        self.assert_owner();
+        let code = env::input().expect("Code to stage is not attached.");
+        assert!(!code.is_empty(), "Code to stage is empty.");
+        let hash: CryptoHash = env::sha256(&code)
+            .try_into()
+            .expect("Hash of code should be 32 bytes.");
+        self.staged_code.set(&code);
+        self.staged_code_hash = Some(hash);
+        self.staged_code_timestamp = env::block_timestamp();
+        log!(
+            "Code with hash '{}' is staged, it can be deployed after {}.",
+            String::from(&Base58CryptoHash::from(hash)),
+            self.staged_code_timestamp + STAGED_CODE_DELAY_IN_NANOS
+        );
+    }
+
+    fn deploy_staged_code(&mut self) -> Promise {
+        self.assert_owner();
+        let code = self.staged_code.get().expect("No staged code.");
+        assert!(
+            env::block_timestamp() >= self.staged_code_timestamp + STAGED_CODE_DELAY_IN_NANOS,
+            "Staged code can not be deployed before {}.",
+            self.staged_code_timestamp + STAGED_CODE_DELAY_IN_NANOS
+        );
+        // deploying and migrating are in one batch, the new code is reverted if migrating fails.
+        // the staged code is kept until it's removed by `migrate` of the new code.
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                vec![],
+                0,
+                Gas::ONE_TERA.mul(T_GAS_FOR_MIGRATE),
+            )
+    }
+}
+
+#[near_bindgen]
+impl TokenConvertor {
+    /// migrate contract state from the released version, or keep the state of the current version.
+    /// the staged code is removed since it has been deployed.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut contract = match read_state_version() {
+            None => Self::internal_migrate_from_v1(),
+            Some(STATE_VERSION) => env::state_read().expect("Failed to read state."),
+            Some(version) => panic!("Unknown state version {}.", version),
+        };
+        contract.staged_code.remove();
+        contract.staged_code_hash = None;
+        contract.staged_code_timestamp = 0;
+        write_state_version();
+        contract
+    }
+
+    /// pools and accounts are migrated lazily when they are used, or by `migrate_pools`,
+    /// so that migrating doesn't depend on the number of pools.
+    fn internal_migrate_from_v1() -> Self {
+        let mut old: TokenConvertorV1 = env::state_read().expect("Failed to read old state.");
+        // whitelist is small, so it's rewritten eagerly.
+        let old_whitelisted_tokens = old.whitelisted_tokens.values().collect_vec();
+        old.whitelisted_tokens.clear();
+        let mut whitelisted_tokens = UnorderedMap::new(StorageKey::WhitelistedTokens);
+        for token in old_whitelisted_tokens {
+            let token: FtMetaData = token.into();
+            whitelisted_tokens.insert(&token.token_id, &token);
+        }
+        let legacy_pool_count = old.pools.len();
+        Self {
+            owner: old.owner,
+            accounts: old.accounts,
+            pools: old.pools,
+            pool_index: PoolIndex::new(),
+            quota_usages: LookupMap::new(StorageKey::QuotaUsages),
+            pool_quota_accounts: LookupMap::new(StorageKey::PoolQuotaAccounts),
+            pool_allowlists: LookupMap::new(StorageKey::PoolAllowlists),
+            token_liabilities: LookupMap::new(StorageKey::TokenLiabilities),
+            token_in_flight_amounts: LookupMap::new(StorageKey::TokenInFlightAmounts),
+            paused_tokens: UnorderedSet::new(StorageKey::PausedTokens),
+            lost_found: LookupMap::new(StorageKey::LostFound),
+            storage_sponsor_budget: 0,
+            whitelisted_tokens,
+            delisted_tokens: UnorderedMap::new(StorageKey::DelistedTokens),
+            create_pool_deposit: old.create_pool_deposit,
+            pool_id: old.pool_id,
+            contract_is_paused: old.contract_is_paused,
+            // no code is staged in the released version.
+            staged_code: LazyOption::new(StorageKey::StagedCode, None),
+            staged_code_hash: None,
+            staged_code_timestamp: 0,
+            legacy_pool_count,
+            legacy_accounts_migrated: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::VAccount;
+    use crate::contract_interfaces::ConvertorViewer;
+    use crate::contract_interfaces::SolvencyAction;
+    use crate::conversion_pool::VPool;
+    use crate::legacy::{AccountV1, ConversionPoolV1, FtMetaDataV1};
+    use crate::test::{setup_contract, usdc, usdt};
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+    use std::collections::HashMap;
+
+    /// the released version doesn't record its state version.
+    fn write_v1_state(old: &TokenConvertorV1) {
+        env::state_write(old);
+        env::storage_remove(&StorageKey::StateVersion.into_storage_key());
+    }
+
+    #[test]
+    #[should_panic(expected = "Code to stage is empty.")]
+    fn test_stage_empty_code() {
+        let (mut context, mut contract, owner) = setup_contract();
+        context.context.input = vec![];
+        testing_env!(context.predecessor_account_id(owner).build());
+        contract.stage_code();
+    }
+
+    #[test]
+    fn test_stage_and_deploy_code() {
+        let (mut context, mut contract, owner) = setup_contract();
+        let code = vec![1u8, 2, 3];
+        context.context.input = code.clone();
+        testing_env!(context.predecessor_account_id(owner.clone()).build());
+        contract.stage_code();
+        let hash: CryptoHash = env::sha256(&code).try_into().unwrap();
+        assert_eq!(contract.get_staged_code_hash(), Some(hash.into()));
+
+        testing_env!(context
+            .block_timestamp(contract.staged_code_timestamp + STAGED_CODE_DELAY_IN_NANOS)
+            .build());
+        contract.deploy_staged_code();
+        // the staged code is kept if migrating fails.
+        assert_eq!(contract.get_staged_code_hash(), Some(hash.into()));
+
+        env::state_write(&contract);
+        let contract = TokenConvertor::migrate();
+        assert_eq!(contract.owner, owner);
+        assert_eq!(contract.get_staged_code_hash(), None);
+        assert!(contract.staged_code.get().is_none());
+        assert_eq!(contract.staged_code_timestamp, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown state version 3.")]
+    fn test_migrate_unknown_state_version() {
+        let (_, contract, _) = setup_contract();
+        env::state_write(&contract);
+        env::storage_write(
+            &StorageKey::StateVersion.into_storage_key(),
+            &3u32.try_to_vec().unwrap(),
+        );
+        TokenConvertor::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Staged code can not be deployed before")]
+    fn test_deploy_code_before_delay() {
+        let (mut context, mut contract, owner) = setup_contract();
+        context.context.input = vec![1u8, 2, 3];
+        testing_env!(context.predecessor_account_id(owner.clone()).build());
+        contract.stage_code();
+        contract.deploy_staged_code();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let (_, _, owner) = setup_contract();
+        let mut old = TokenConvertorV1 {
+            owner: owner.clone(),
+            accounts: LookupMap::new(StorageKey::Accounts),
+            pools: UnorderedMap::new(StorageKey::Pools),
+            whitelisted_tokens: UnorderedMap::new(StorageKey::WhitelistedTokens),
+            create_pool_deposit: 1,
+            pool_id: 2,
+            contract_is_paused: false,
+        };
+        old.whitelisted_tokens.insert(
+            &usdt(),
+            &FtMetaDataV1 {
+                token_id: usdt(),
+                decimals: 6,
+            },
+        );
+        old.pools.insert(
+            &U64(1),
+            &VPool::V1(ConversionPoolV1 {
+                id: U64(1),
+                creator: owner.clone(),
+                in_token: usdc(),
+                in_token_balance: U128(10),
+                out_token: usdt(),
+                out_token_balance: U128(20),
+                reversible: false,
+                in_token_rate: 1,
+                out_token_rate: 1,
+                deposit_near_amount: U128(1),
+            }),
+        );
+        old.accounts.insert(
+            &owner,
+            &VAccount::V1(AccountV1 {
+                near_amount_for_storage: 10u128.pow(24),
+                tokens: HashMap::from([(usdt(), 5)]),
+                ft_transfer_lock: 0,
+            }),
+        );
+        write_v1_state(&old);
+
+        let mut contract = TokenConvertor::migrate();
+        assert_eq!(contract.owner, owner);
+        assert_eq!(contract.legacy_pool_count, 1);
+        assert_eq!(contract.get_token_liabilities(usdc()), U128(0));
+        // indexes and liabilities of pools are built when they are migrated.
+        assert_eq!(contract.migrate_pools(0, 10), 1);
+        assert_eq!(contract.legacy_pool_count, 0);
+        assert_eq!(contract.get_pools_by_pair(usdc(), usdt()).len(), 1);
+        assert_eq!(contract.pool_id, 2);
+        let token = contract.get_whitelisted_token(usdt()).unwrap();
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.symbol, None);
+        assert!(token.tags.is_empty());
+        assert_eq!(contract.get_whitelist(0, 10).len(), 1);
+        assert_eq!(contract.get_token_liabilities(usdc()), U128(10));
+        assert_eq!(contract.get_token_liabilities(usdt()), U128(20));
+        assert_eq!(contract.get_staged_code_hash(), None);
+        assert!(contract.staged_code.get().is_none());
+        assert_eq!(contract.staged_code_timestamp, 0);
+
+        // token balances of the account are counted when it's migrated.
+        assert_eq!(contract.migrate_accounts(vec![owner.clone(), usdc()]), 1);
+        assert_eq!(contract.get_token_liabilities(usdt()), U128(25));
+        assert_eq!(contract.migrate_accounts(vec![owner.clone()]), 0);
+        contract.internal_use_account(&owner, |_| {});
+        assert_eq!(contract.get_token_liabilities(usdt()), U128(25));
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(owner.clone())
+            .build());
+        contract.finish_migrating_accounts();
+        contract.assert_legacy_state_migrated();
+
+        // the state isn't migrated again when the code is redeployed.
+        env::state_write(&contract);
+        let contract = TokenConvertor::migrate();
+        assert_eq!(contract.legacy_pool_count, 0);
+        assert!(contract.legacy_accounts_migrated);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pools or accounts of the released version are not migrated yet.")]
+    fn test_reconcile_before_migrating_accounts() {
+        let (_, _, owner) = setup_contract();
+        let old = TokenConvertorV1 {
+            owner,
+            accounts: LookupMap::new(StorageKey::Accounts),
+            pools: UnorderedMap::new(StorageKey::Pools),
+            whitelisted_tokens: UnorderedMap::new(StorageKey::WhitelistedTokens),
+            create_pool_deposit: 1,
+            pool_id: 0,
+            contract_is_paused: false,
+        };
+        write_v1_state(&old);
+        let mut contract = TokenConvertor::migrate();
+        contract.reconcile(usdt());
+    }
+}
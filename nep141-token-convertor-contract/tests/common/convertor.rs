@@ -1,9 +1,10 @@
+use near_contract_standards::storage_management::StorageBalance;
 use near_sdk::json_types::U128;
 use near_sdk::serde_json::json;
 use near_sdk::{serde_json, AccountId, Balance};
 use near_units::parse_near;
 use nep141_token_convertor_contract::account::AccountView;
-use nep141_token_convertor_contract::conversion_pool::ConversionPool;
+use nep141_token_convertor_contract::conversion_pool::{ConversionPool, TimeWindow};
 use nep141_token_convertor_contract::types::PoolId;
 use nep141_token_convertor_contract::FtMetaData;
 use workspaces::network::Sandbox;
@@ -93,11 +94,99 @@ impl ConvertorContract {
             .unwrap()
     }
 
-    pub async fn get_whitelist(&self, worker: &Worker<Sandbox>) -> Vec<FtMetaData> {
+    pub async fn get_pools_by_pair(
+        &self,
+        worker: &Worker<Sandbox>,
+        in_token: AccountId,
+        out_token: AccountId,
+    ) -> Vec<ConversionPool> {
+        worker
+            .view(
+                &self.contract_id,
+                "get_pools_by_pair",
+                json!((in_token, out_token)).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn get_pools_by_creator(
+        &self,
+        worker: &Worker<Sandbox>,
+        account_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<ConversionPool> {
+        worker
+            .view(
+                &self.contract_id,
+                "get_pools_by_creator",
+                json!((account_id, from_index, limit))
+                    .to_string()
+                    .into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn get_whitelist(
+        &self,
+        worker: &Worker<Sandbox>,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<FtMetaData> {
         worker
             .view(
                 &self.contract_id,
                 "get_whitelist",
+                json!((from_index, limit)).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn get_whitelisted_token(
+        &self,
+        worker: &Worker<Sandbox>,
+        token_id: AccountId,
+    ) -> Option<FtMetaData> {
+        worker
+            .view(
+                &self.contract_id,
+                "get_whitelisted_token",
+                json!({ "token_id": token_id }).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn set_whitelisted_token_tags(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &Account,
+        token_id: AccountId,
+        tags: Vec<String>,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "set_whitelisted_token_tags")
+            .args_json(json!({ "token_id": token_id, "tags": tags }))?
+            .transact()
+            .await
+    }
+
+    pub async fn get_delisted_tokens(&self, worker: &Worker<Sandbox>) -> Vec<FtMetaData> {
+        worker
+            .view(
+                &self.contract_id,
+                "get_delisted_tokens",
                 json!(()).to_string().into_bytes(),
             )
             .await
@@ -106,6 +195,25 @@ impl ConvertorContract {
             .unwrap()
     }
 
+    pub async fn get_pools_by_delisted_token(
+        &self,
+        worker: &Worker<Sandbox>,
+        token_id: AccountId,
+    ) -> Vec<ConversionPool> {
+        worker
+            .view(
+                &self.contract_id,
+                "get_pools_by_delisted_token",
+                json!({ "token_id": token_id, "from_index": 0, "limit": 100 })
+                    .to_string()
+                    .into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
     pub async fn extend_whitelisted_tokens(
         &self,
         worker: &Worker<Sandbox>,
@@ -119,6 +227,20 @@ impl ConvertorContract {
             .await
     }
 
+    pub async fn whitelist_token(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &Account,
+        token_id: near_sdk::AccountId,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "whitelist_token")
+            .max_gas()
+            .args_json(json!({ "token_id": token_id }))?
+            .transact()
+            .await
+    }
+
     pub async fn remove_whitelisted_tokens(
         &self,
         worker: &Worker<Sandbox>,
@@ -169,6 +291,7 @@ impl ConvertorContract {
                 is_reversible,
                 in_token_rate,
                 out_token_rate,
+                Option::<TimeWindow>::None,
             )))?
             .transact()
             .await
@@ -223,4 +346,100 @@ impl ConvertorContract {
             .transact()
             .await
     }
+
+    pub async fn storage_balance_of(
+        &self,
+        worker: &Worker<Sandbox>,
+        account_id: &workspaces::AccountId,
+    ) -> Option<StorageBalance> {
+        worker
+            .view(
+                &self.contract_id,
+                "storage_balance_of",
+                json!({ "account_id": account_id }).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn storage_unregister(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &Account,
+        force: Option<bool>,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "storage_unregister")
+            .deposit(1)
+            .max_gas()
+            .args_json(json!({ "force": force }))?
+            .transact()
+            .await
+    }
+
+    pub async fn get_token_liabilities(
+        &self,
+        worker: &Worker<Sandbox>,
+        token_id: AccountId,
+    ) -> U128 {
+        worker
+            .view(
+                &self.contract_id,
+                "get_token_liabilities",
+                json!({ "token_id": token_id }).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn get_storage_sponsor_budget(
+        &self,
+        worker: &Worker<Sandbox>,
+        pool_id: Option<PoolId>,
+    ) -> U128 {
+        worker
+            .view(
+                &self.contract_id,
+                "get_storage_sponsor_budget",
+                json!({ "pool_id": pool_id }).to_string().into_bytes(),
+            )
+            .await
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    pub async fn deposit_storage_sponsor_budget(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &Account,
+        pool_id: Option<PoolId>,
+        amount: Balance,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "deposit_storage_sponsor_budget")
+            .deposit(amount)
+            .args_json(json!({ "pool_id": pool_id }))?
+            .transact()
+            .await
+    }
+
+    pub async fn recover_surplus(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &Account,
+        token_id: AccountId,
+        receiver_id: AccountId,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "recover_surplus")
+            .max_gas()
+            .args_json(json!({ "token_id": token_id, "receiver_id": receiver_id }))?
+            .transact()
+            .await
+    }
 }
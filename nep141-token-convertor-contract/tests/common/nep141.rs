@@ -2,7 +2,7 @@ use near_sdk::json_types::U128;
 use near_sdk::serde_json::json;
 use workspaces::network::Sandbox;
 use workspaces::result::CallExecutionDetails;
-use workspaces::{Account, AccountId, Worker};
+use workspaces::{AccountId, Worker};
 
 pub struct Nep141 {
     pub account: workspaces::Account,
@@ -28,6 +28,35 @@ impl Nep141 {
             .await
     }
 
+    pub async fn ft_transfer(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &workspaces::Account,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "ft_transfer")
+            .deposit(1)
+            .args_json(json!({ "receiver_id": receiver_id, "amount": amount }))?
+            .transact()
+            .await
+    }
+
+    pub async fn storage_unregister(
+        &self,
+        worker: &Worker<Sandbox>,
+        signer: &workspaces::Account,
+        force: Option<bool>,
+    ) -> anyhow::Result<CallExecutionDetails> {
+        signer
+            .call(worker, &self.contract_id, "storage_unregister")
+            .deposit(1)
+            .args_json(json!({ "force": force }))?
+            .transact()
+            .await
+    }
+
     pub async fn mint(
         &self,
         worker: &Worker<Sandbox>,
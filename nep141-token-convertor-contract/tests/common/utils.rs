@@ -11,6 +11,18 @@ pub const CONVERTOR_WASM_BYTES: &[u8] =
     include_bytes!("../../../res/nep141_token_convertor_contract.wasm");
 pub const TEST_TOKEN_WASM_BYTES: &[u8] = include_bytes!("../../../res/test_token.wasm");
 
+pub fn ft_metadata(token_id: String, decimals: u8) -> FtMetaData {
+    FtMetaData {
+        token_id: near_sdk::AccountId::new_unchecked(token_id),
+        decimals,
+        symbol: None,
+        name: None,
+        icon: None,
+        reference: None,
+        tags: vec![],
+    }
+}
+
 pub async fn register_account(worker: &Worker<Sandbox>, root: &Account, name: &str) -> Account {
     let tt = root
         .create_subaccount(worker, name)
@@ -53,18 +65,9 @@ pub async fn setup_pools() -> (
     deploy_test_token_contract(&worker, &usdn, accounts_to_register.clone()).await;
 
     let whitelist_tokens = vec![
-        FtMetaData {
-            token_id: near_sdk::AccountId::new_unchecked(usdt.id().to_string()),
-            decimals: 6,
-        },
-        FtMetaData {
-            token_id: near_sdk::AccountId::new_unchecked(usdc.id().to_string()),
-            decimals: 6,
-        },
-        FtMetaData {
-            token_id: near_sdk::AccountId::new_unchecked(usdn.id().to_string()),
-            decimals: 6,
-        },
+        ft_metadata(usdt.id().to_string(), 6),
+        ft_metadata(usdc.id().to_string(), 6),
+        ft_metadata(usdn.id().to_string(), 6),
     ];
 
     let convertor_contract = ConvertorContract::deploy(&worker, &convertor, &owner).await;
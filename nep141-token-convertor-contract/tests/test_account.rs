@@ -0,0 +1,244 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde_json::json;
+use near_units::parse_near;
+
+use crate::common::utils::{register_account, setup_pools};
+use nep141_token_convertor_contract::token_receiver::ConvertAction;
+use nep141_token_convertor_contract::token_receiver::TransferMessage::{AddLiquidity, Convert};
+
+mod common;
+
+fn convert_msg(input_token_id: near_sdk::AccountId, amount: u128) -> String {
+    json!(Convert {
+        convert_action: ConvertAction {
+            pool_id: U64(1),
+            input_token_id,
+            input_token_amount: U128::from(amount),
+            allow_partial: true,
+            deadline: None,
+        }
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn test_sponsor_storage() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            false,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+    convertor_contract
+        .deposit_storage_sponsor_budget(&worker, &creator, Some(U64(1)), parse_near!("1 N"))
+        .await
+        .unwrap();
+
+    let token0 = &token_contracts[0];
+    let token1 = &token_contracts[1];
+    let guest = register_account(&worker, &root, "guest").await;
+    for token in [token0, token1] {
+        token
+            .storage_deposit(&worker, &guest, None, None, parse_near!("0.00125 N"))
+            .await;
+    }
+    token0
+        .mint(&worker, guest.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+
+    // the pool has no balance, so all of the input is refunded and nothing is sponsored.
+    token0
+        .ft_transfer_call(
+            &worker,
+            &guest,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            convert_msg(whitelist_tokens[0].token_id.clone(), 10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        100,
+        token0.ft_balance_of(&worker, guest.id().clone()).await.0
+    );
+    assert!(convertor_contract
+        .storage_balance_of(&worker, guest.id())
+        .await
+        .is_none());
+    assert_eq!(
+        parse_near!("1 N"),
+        convertor_contract
+            .get_storage_sponsor_budget(&worker, Some(U64(1)))
+            .await
+            .0
+    );
+
+    token1
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token1
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+    token0
+        .ft_transfer_call(
+            &worker,
+            &guest,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            convert_msg(whitelist_tokens[0].token_id.clone(), 10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        10,
+        token1.ft_balance_of(&worker, guest.id().clone()).await.0
+    );
+    let storage_balance = convertor_contract
+        .storage_balance_of(&worker, guest.id())
+        .await
+        .expect("guest should be registered by the sponsor.");
+    assert_eq!(0, storage_balance.available.0);
+    assert_eq!(
+        parse_near!("1 N") - storage_balance.total.0,
+        convertor_contract
+            .get_storage_sponsor_budget(&worker, Some(U64(1)))
+            .await
+            .0
+    );
+
+    // the sponsored storage goes back to the budget of the pool.
+    convertor_contract
+        .storage_unregister(&worker, &guest, Some(true))
+        .await
+        .unwrap();
+    assert!(convertor_contract
+        .storage_balance_of(&worker, guest.id())
+        .await
+        .is_none());
+    assert_eq!(
+        parse_near!("1 N"),
+        convertor_contract
+            .get_storage_sponsor_budget(&worker, Some(U64(1)))
+            .await
+            .0
+    );
+    assert_eq!(
+        0,
+        convertor_contract
+            .get_storage_sponsor_budget(&worker, None)
+            .await
+            .0
+    );
+}
+
+#[tokio::test]
+async fn test_force_unregister() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            false,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+    let token0 = &token_contracts[0];
+    let token1 = &token_contracts[1];
+    token1
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token1
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+
+    // the output can't be sent to the user, so it's kept in the account.
+    token1
+        .storage_unregister(&worker, &user, Some(true))
+        .await
+        .unwrap();
+    token0
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token0
+        .ft_transfer_call(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            convert_msg(whitelist_tokens[0].token_id.clone(), 10),
+        )
+        .await
+        .unwrap();
+    let user_id = near_sdk::AccountId::new_unchecked(user.id().to_string());
+    let account = convertor_contract
+        .get_account(&worker, user_id.clone())
+        .await;
+    assert_eq!(
+        Some(&U128(10)),
+        account.tokens.get(&whitelist_tokens[1].token_id)
+    );
+
+    token1
+        .storage_deposit(&worker, &user, None, None, parse_near!("0.00125 N"))
+        .await;
+    convertor_contract
+        .storage_unregister(&worker, &user, Some(true))
+        .await
+        .unwrap();
+    assert_eq!(
+        10,
+        token1.ft_balance_of(&worker, user.id().clone()).await.0,
+        "tokens in the account should be sent when unregistering by force."
+    );
+    assert!(convertor_contract
+        .storage_balance_of(&worker, user.id())
+        .await
+        .is_none());
+    assert_eq!(
+        0,
+        convertor_contract
+            .get_token_liabilities(&worker, whitelist_tokens[1].token_id.clone())
+            .await
+            .0
+    );
+}
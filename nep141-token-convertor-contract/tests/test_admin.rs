@@ -2,7 +2,7 @@ use near_sdk::json_types::U128;
 use nep141_token_convertor_contract::FtMetaData;
 use workspaces::prelude::*;
 
-use crate::common::utils::setup_pools;
+use crate::common::utils::{ft_metadata, setup_pools};
 
 mod common;
 
@@ -17,33 +17,21 @@ async fn test_whitelist() {
 
     let mut tokens: Vec<FtMetaData> = whitelist_tokens
         .iter()
-        .map(|e| FtMetaData {
-            token_id: e.token_id.clone(),
-            decimals: 6,
-        })
+        .map(|e| ft_metadata(e.token_id.to_string(), 6))
         .collect();
     tokens[2].decimals = 8;
 
     let mut tokens = vec![
-        FtMetaData {
-            token_id: usdt_id.clone(),
-            decimals: 6,
-        },
-        FtMetaData {
-            token_id: usdc_id.clone(),
-            decimals: 6,
-        },
-        FtMetaData {
-            token_id: usdn_id.clone(),
-            decimals: 8,
-        },
+        ft_metadata(usdt_id.to_string(), 6),
+        ft_metadata(usdc_id.to_string(), 6),
+        ft_metadata(usdn_id.to_string(), 8),
     ];
     convertor_contract
         .extend_whitelisted_tokens(&worker, &owner, tokens.clone())
         .await
         .unwrap();
     assert_eq!(
-        convertor_contract.get_whitelist(&worker).await,
+        convertor_contract.get_whitelist(&worker, 0, 100).await,
         tokens,
         "extend whitelist not right "
     );
@@ -56,10 +44,15 @@ async fn test_whitelist() {
         .await
         .unwrap();
     assert_eq!(
-        convertor_contract.get_whitelist(&worker).await,
+        convertor_contract.get_whitelist(&worker, 0, 100).await,
         tokens,
         "remove token not right"
     );
+    assert_eq!(
+        convertor_contract.get_whitelist(&worker, 1, 100).await,
+        vec![],
+        "get whitelist from index not right"
+    );
     assert!(
         convertor_contract
             .extend_whitelisted_tokens(&worker, &root, tokens.clone())
@@ -93,3 +86,73 @@ async fn test_set_pool_create_deposit_amount() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_whitelist_token_by_metadata() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    let usdt_id = whitelist_tokens[0].token_id.clone();
+    convertor_contract
+        .remove_whitelisted_tokens(&worker, &owner, vec![usdt_id.clone()])
+        .await
+        .unwrap();
+
+    assert!(
+        convertor_contract
+            .whitelist_token(&worker, &root, usdt_id.clone())
+            .await
+            .is_err(),
+        "should failed by owner access check"
+    );
+    convertor_contract
+        .whitelist_token(&worker, &owner, usdt_id.clone())
+        .await
+        .unwrap();
+    let token = convertor_contract
+        .get_whitelisted_token(&worker, usdt_id.clone())
+        .await
+        .expect("token should be whitelisted");
+    assert_eq!(token.decimals, 6);
+    assert_eq!(token.symbol, Some(usdt_id.to_string()));
+
+    // user account has no contract, so getting metadata fails.
+    let user_id = near_sdk::AccountId::new_unchecked(user.id().to_string());
+    let result = convertor_contract
+        .whitelist_token(&worker, &owner, user_id.clone())
+        .await;
+    assert!(result.is_err() || !result.unwrap().is_success());
+    assert!(convertor_contract
+        .get_whitelist(&worker, 0, 100)
+        .await
+        .iter()
+        .all(|e| e.token_id != user_id));
+}
+
+#[tokio::test]
+async fn test_set_whitelisted_token_tags() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    let usdt_id = whitelist_tokens[0].token_id.clone();
+    let tags = vec!["stablecoin".to_string()];
+    assert!(
+        convertor_contract
+            .set_whitelisted_token_tags(&worker, &root, usdt_id.clone(), tags.clone())
+            .await
+            .is_err(),
+        "should failed by owner access check"
+    );
+    convertor_contract
+        .set_whitelisted_token_tags(&worker, &owner, usdt_id.clone(), tags.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        convertor_contract
+            .get_whitelisted_token(&worker, usdt_id.clone())
+            .await
+            .unwrap()
+            .tags,
+        tags
+    );
+}
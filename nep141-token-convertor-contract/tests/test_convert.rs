@@ -1,11 +1,12 @@
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde_json::json;
 use near_units::parse_near;
-use workspaces::prelude::*;
 
 use crate::common::utils::setup_pools;
-use nep141_token_convertor_contract::token_receiver::ConvertAction;
-use nep141_token_convertor_contract::token_receiver::TransferMessage::{AddLiquidity, Convert};
+use nep141_token_convertor_contract::token_receiver::TransferMessage::{
+    AddLiquidity, Convert, ConvertSplit,
+};
+use nep141_token_convertor_contract::token_receiver::{ConvertAction, ConvertSplitAction};
 
 mod common;
 
@@ -57,7 +58,9 @@ pub async fn test_convert() {
         convert_action: ConvertAction {
             pool_id: U64(1),
             input_token_id: near_sdk::AccountId::new_unchecked(token_out.contract_id.to_string()),
-            input_token_amount: U128::from(10)
+            input_token_amount: U128::from(10),
+            allow_partial: false,
+            deadline: None,
         }
     })
     .to_string();
@@ -86,3 +89,152 @@ pub async fn test_convert() {
         "user token balance should be 10."
     );
 }
+
+#[tokio::test]
+pub async fn test_convert_split() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    let token_in = &token_contracts[0];
+    let token_out = &token_contracts[1];
+    token_out
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    for pool_id in 1..=2 {
+        convertor_contract
+            .create_pool(
+                &worker,
+                &creator,
+                whitelist_tokens[0].token_id.clone(),
+                whitelist_tokens[1].token_id.clone(),
+                false,
+                1,
+                1,
+                Some(parse_near!("1 N")),
+            )
+            .await
+            .unwrap();
+        token_out
+            .ft_transfer_call(
+                &worker,
+                &creator,
+                convertor_contract.contract_id.clone(),
+                U128::from(10),
+                Option::None,
+                json!(AddLiquidity {
+                    pool_id: U64(pool_id)
+                })
+                .to_string(),
+            )
+            .await
+            .unwrap();
+    }
+
+    token_in
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    let convert_msg = json!(ConvertSplit {
+        convert_split_action: ConvertSplitAction {
+            input_token_id: whitelist_tokens[0].token_id.clone(),
+            input_token_amount: U128::from(15),
+            output_token_id: whitelist_tokens[1].token_id.clone(),
+            legs: None,
+        }
+    })
+    .to_string();
+    token_in
+        .ft_transfer_call(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(15),
+            Option::None,
+            convert_msg,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        15,
+        token_out.ft_balance_of(&worker, user.id().clone()).await.0,
+        "user should receive outputs of both pools."
+    );
+    let pools = convertor_contract.get_pools(&worker, 0, 10).await;
+    assert_eq!(pools.iter().map(|e| e.out_token_balance.0).sum::<u128>(), 5);
+}
+
+#[tokio::test]
+pub async fn test_convert_partially() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            false,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+
+    let token_in = &token_contracts[0];
+    let token_out = &token_contracts[1];
+    token_out
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token_out
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(6),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+
+    token_in
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    let convert_msg = json!(Convert {
+        convert_action: ConvertAction {
+            pool_id: U64(1),
+            input_token_id: whitelist_tokens[0].token_id.clone(),
+            input_token_amount: U128::from(10),
+            allow_partial: true,
+            deadline: None,
+        }
+    })
+    .to_string();
+    token_in
+        .ft_transfer_call(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            convert_msg,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        6,
+        token_out.ft_balance_of(&worker, user.id().clone()).await.0
+    );
+    assert_eq!(
+        94,
+        token_in.ft_balance_of(&worker, user.id().clone()).await.0,
+        "unfilled input token should be refunded."
+    );
+}
@@ -4,7 +4,8 @@ use near_units::parse_near;
 use workspaces::prelude::*;
 
 use crate::common::utils::setup_pools;
-use nep141_token_convertor_contract::token_receiver::TransferMessage::AddLiquidity;
+use nep141_token_convertor_contract::token_receiver::ConvertAction;
+use nep141_token_convertor_contract::token_receiver::TransferMessage::{AddLiquidity, Convert};
 
 mod common;
 
@@ -26,6 +27,24 @@ async fn test_create_pool() {
         )
         .await
         .unwrap();
+    assert_eq!(
+        convertor_contract
+            .get_pools_by_creator(&worker, creator.id().to_string().parse().unwrap(), 0, 10)
+            .await
+            .len(),
+        1
+    );
+    assert_eq!(
+        convertor_contract
+            .get_pools_by_pair(
+                &worker,
+                whitelist_tokens[0].token_id.clone(),
+                whitelist_tokens[1].token_id.clone()
+            )
+            .await
+            .len(),
+        1
+    );
     convertor_contract.set_deposit_amount_of_pool_creation(
         &worker,
         &owner,
@@ -82,7 +101,8 @@ async fn test_deposit_withdraw_delete() {
             true,
             1,
             1,
-            Option::Some(1),
+            // the deposit pays the storage of pool, the exceeding part is refunded.
+            Option::Some(parse_near!("0.1N")),
         )
         .await
         .unwrap();
@@ -173,3 +193,196 @@ async fn test_deposit_withdraw_delete() {
     let balance = token0.ft_balance_of(&worker, creator.id().clone()).await;
     assert_eq!(100, balance.0);
 }
+
+#[tokio::test]
+async fn test_delist_token_used_by_pool() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            true,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+
+    let token0 = &token_contracts[0];
+    token0
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token0
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+
+    let usdt_id = whitelist_tokens[0].token_id.clone();
+    convertor_contract
+        .remove_whitelisted_tokens(&worker, &owner, vec![usdt_id.clone()])
+        .await
+        .unwrap();
+    assert_eq!(
+        convertor_contract.get_delisted_tokens(&worker).await,
+        vec![whitelist_tokens[0].clone()]
+    );
+    assert_eq!(
+        convertor_contract
+            .get_pools_by_delisted_token(&worker, usdt_id.clone())
+            .await
+            .len(),
+        1
+    );
+
+    let token1 = &token_contracts[1];
+    token1
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token1
+        .ft_transfer_call(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(Convert {
+                convert_action: ConvertAction {
+                    pool_id: U64(1),
+                    input_token_id: whitelist_tokens[1].token_id.clone(),
+                    input_token_amount: U128::from(10),
+                    allow_partial: false,
+                    deadline: None,
+                }
+            })
+            .to_string(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        100,
+        token1.ft_balance_of(&worker, user.id().clone()).await.0,
+        "conversion should be refunded since the token is delisted."
+    );
+
+    convertor_contract
+        .withdraw_token_in_pool(&worker, &creator, U64(1), usdt_id.clone(), Option::None)
+        .await
+        .unwrap();
+    convertor_contract
+        .delete_pool(&worker, &creator, U64(1))
+        .await
+        .unwrap();
+    assert!(convertor_contract
+        .get_delisted_tokens(&worker)
+        .await
+        .is_empty());
+    assert_eq!(
+        100,
+        token0.ft_balance_of(&worker, creator.id().clone()).await.0
+    );
+}
+
+#[tokio::test]
+async fn test_failed_withdrawal_restored_to_pool() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            false,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+
+    let token0 = &token_contracts[0];
+    let token1 = &token_contracts[1];
+    token1
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token1
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+    token0
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token0
+        .ft_transfer_call(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(Convert {
+                convert_action: ConvertAction {
+                    pool_id: U64(1),
+                    input_token_id: whitelist_tokens[0].token_id.clone(),
+                    input_token_amount: U128::from(10),
+                    allow_partial: false,
+                    deadline: None,
+                }
+            })
+            .to_string(),
+        )
+        .await
+        .unwrap();
+
+    // the creator can't receive the in token of the non-reversible pool anymore.
+    token0
+        .storage_unregister(&worker, &creator, Some(true))
+        .await
+        .unwrap();
+    convertor_contract
+        .withdraw_token_in_pool(
+            &worker,
+            &creator,
+            U64(1),
+            whitelist_tokens[0].token_id.clone(),
+            Option::None,
+        )
+        .await
+        .unwrap();
+
+    let pool = convertor_contract.get_pools(&worker, 0, 10).await[0].clone();
+    assert_eq!(
+        10, pool.in_token_balance.0,
+        "in token of the failed withdrawal should be restored to the pool."
+    );
+    assert_eq!(
+        10,
+        convertor_contract
+            .get_token_liabilities(&worker, whitelist_tokens[0].token_id.clone())
+            .await
+            .0
+    );
+}
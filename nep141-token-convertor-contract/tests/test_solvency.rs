@@ -0,0 +1,104 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde_json::json;
+use near_units::parse_near;
+
+use crate::common::utils::setup_pools;
+use nep141_token_convertor_contract::token_receiver::TransferMessage::AddLiquidity;
+
+mod common;
+
+#[tokio::test]
+async fn test_recover_surplus() {
+    let (worker, whitelist_tokens, token_contracts, convertor_contract, root, owner, creator, user) =
+        setup_pools().await;
+
+    convertor_contract
+        .create_pool(
+            &worker,
+            &creator,
+            whitelist_tokens[0].token_id.clone(),
+            whitelist_tokens[1].token_id.clone(),
+            true,
+            1,
+            1,
+            Some(parse_near!("1 N")),
+        )
+        .await
+        .unwrap();
+
+    let token0 = &token_contracts[0];
+    token0
+        .mint(&worker, creator.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token0
+        .ft_transfer_call(
+            &worker,
+            &creator,
+            convertor_contract.contract_id.clone(),
+            U128::from(10),
+            Option::None,
+            json!(AddLiquidity { pool_id: U64(1) }).to_string(),
+        )
+        .await
+        .unwrap();
+    // tokens sent by ft_transfer are not credited to anyone.
+    token0
+        .mint(&worker, user.id().clone(), U128::from(100))
+        .await
+        .unwrap();
+    token0
+        .ft_transfer(
+            &worker,
+            &user,
+            convertor_contract.contract_id.clone(),
+            U128::from(5),
+        )
+        .await
+        .unwrap();
+
+    let token_id = whitelist_tokens[0].token_id.clone();
+    convertor_contract
+        .recover_surplus(
+            &worker,
+            &owner,
+            token_id.clone(),
+            near_sdk::AccountId::new_unchecked(creator.id().to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        95,
+        token0.ft_balance_of(&worker, creator.id().clone()).await.0,
+        "only the surplus should be recovered."
+    );
+    assert_eq!(
+        10,
+        token0
+            .ft_balance_of(&worker, convertor_contract.contract_id.clone())
+            .await
+            .0
+    );
+    assert_eq!(
+        10,
+        convertor_contract
+            .get_token_liabilities(&worker, token_id.clone())
+            .await
+            .0
+    );
+
+    // nothing is left to recover.
+    convertor_contract
+        .recover_surplus(
+            &worker,
+            &owner,
+            token_id,
+            near_sdk::AccountId::new_unchecked(creator.id().to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        95,
+        token0.ft_balance_of(&worker, creator.id().clone()).await.0
+    );
+}
@@ -11,6 +11,12 @@ if [ "$1" == "deploy" ]; then
   near deploy $CONVERTOR_CONTRACT_ACCOUNT_ID ../res/$CONVERTOR_WASM_NAME new '{"owner": "'$OWNER_ACCOUNT_ID'", "create_pool_deposit": "'$CREATE_POOL_DEPOSIT_NEAR_AMOUNT'"}'
 elif [ "$1" == "redeploy" ]; then
   near deploy $CONVERTOR_CONTRACT_ACCOUNT_ID ../res/$CONVERTOR_WASM_NAME
+elif [ "$1" == "migrate" ]; then
+  near deploy $CONVERTOR_CONTRACT_ACCOUNT_ID ../res/$CONVERTOR_WASM_NAME migrate '{}'
+elif [ "$1" == "stage" ]; then
+  near call $CONVERTOR_CONTRACT_ACCOUNT_ID stage_code $(base64 -w 0 ../res/$CONVERTOR_WASM_NAME) --base64 --accountId $OWNER_ACCOUNT_ID --gas 300000000000000
+elif [ "$1" == "deploy-staged" ]; then
+  near call $CONVERTOR_CONTRACT_ACCOUNT_ID deploy_staged_code --accountId $OWNER_ACCOUNT_ID --gas 300000000000000
 elif [ "$1" == "clean" ]; then
   bash clear-state.sh && near deploy $CONVERTOR_CONTRACT_ACCOUNT_ID ../res/$CONVERTOR_WASM_NAME new '{"owner": "'$OWNER_ACCOUNT_ID'", "create_pool_deposit": "'$CREATE_POOL_DEPOSIT_NEAR_AMOUNT'"}'
 fi